@@ -17,6 +17,10 @@ layout(std140, set = 2, binding = 1) uniform State {
     uvec2 padding;
 };
 
+// xyz of a material are texture coordinates, and w is how much it shows
+layout(set = 3, binding = 0) uniform texture3D material_texture;
+layout(set = 3, binding = 1) uniform sampler material_sampler;
+
 const vec4 light_position = vec4(2, 2, 0, 0);
 const vec3 light_color = 5 * vec3(0.5, 0.5, 0.5);
 const vec3 ambient_light = 4 * vec3(0.4, 0.4, 0.45);
@@ -28,6 +32,7 @@ layout(location = 0) in vec4 world_position;
 layout(location = 1) in vec4 normal;
 layout(location = 2) in vec4 color;
 layout(location = 3) in vec3 barycentric;
+layout(location = 4) in vec4 material;
 
 layout(location = 0) out vec4 final_color;
 
@@ -43,7 +48,10 @@ void main() {
     vec3 diffuse = albedo * light_color * max(dot(L, n), 0.0);
     vec3 specular = light_color * pow(clamp(dot(n, H), 0.0, 1.0), shininess);
 
-    final_color = vec4(pow((ambient + diffuse + specular) * color.xyz, vec3(2.2)), color.w);
+    vec3 pattern = texture(sampler3D(material_texture, material_sampler), material.xyz).xyz;
+    vec3 surface = mix(color.xyz, color.xyz * pattern, material.w);
+
+    final_color = vec4(pow((ambient + diffuse + specular) * surface, vec3(2.2)), color.w);
 
     vec3 a3 = step(2.0 * fwidth(barycentric), barycentric);
     if(contacts > 0 || hollow != 0) {
@@ -13,11 +13,16 @@ layout(location = 8) in vec4 color0;
 layout(location = 9) in vec4 color1;
 layout(location = 10) in vec4 color2;
 layout(location = 11) in vec4 color3;
+layout(location = 12) in vec4 material0;
+layout(location = 13) in vec4 material1;
+layout(location = 14) in vec4 material2;
+layout(location = 15) in vec4 material3;
 
 layout(location = 0) out vec4 world_position;
 layout(location = 1) out vec4 normal;
 layout(location = 2) out vec4 color;
 layout(location = 3) out vec3 barycentric;
+layout(location = 4) out vec4 material;
 
 struct Mix {
     uint a;
@@ -56,6 +61,7 @@ void main()
     mat4 positions = mat4(position0, position1, position2, position3);
     mat4 colors = mat4(color0, color1, color2, color3);
     mat4 normals = mat4(normal0, normal1, normal2, normal3);
+    mat4 materials = mat4(material0, material1, material2, material3);
 
     // get world positions of each vertex of tetrahedron
     mat4 world_positions = mat4(transforms.translation, transforms.translation, transforms.translation, transforms.translation) + transforms.linear * positions;
@@ -71,6 +77,7 @@ void main()
     world_position = mix(world_positions[m.a], world_positions[m.b], s);
     normal = mix(normals[m.a], normals[m.b], s);
     color = mix(colors[m.a], colors[m.b], s);
+    material = mix(materials[m.a], materials[m.b], s);
     barycentric = vec3(0);
     barycentric[gl_VertexIndex % 3] = 1;
     gl_Position = camera.projection * vec4((camera.view * (world_position - camera.position)).xyz, 1.0);
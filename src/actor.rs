@@ -1,6 +1,7 @@
 use crate::collision;
 use crate::constraints;
 use crate::draw_state;
+use crate::ga;
 use crate::input;
 use crate::joint;
 use crate::na;
@@ -16,8 +17,16 @@ pub enum GrabState {
     Hit(hecs::Entity),
 }
 
+// Limits on how near or far a held object can be, how far each scroll step
+// moves it, and how fast it turns with the mouse
+const HOLD_DISTANCE_RANGE: (f32, f32) = (0.5, 10.0);
+const HOLD_SCROLL_STEP: f32 = 0.25;
+const HOLD_ROTATE_SPEED: f32 = 0.0025;
+
 pub struct Actor {
     pub grab_state: GrabState,
+    pub grab_spring: joint::GrabSpring,
+    pub throw_impulse: f32,
     pub move_thrust: f32,
     pub look_torque: f32,
 }
@@ -41,11 +50,14 @@ pub fn update_actor(
                 .unwrap();
             if let Some((hit_entity, t)) = collision::cast_ray(actor_position, forward, world) {
                 let world_space_hit = world.get::<&physics::RigidBody>(actor_entity).unwrap().get_transform() * (t * na::vec4(0.0, 0.0, 1.0, 0.0));
-                let world_to_local = world.get::<&physics::RigidBody>(hit_entity).unwrap().get_transform().inverse();
-                constraints.add_joint(actor_entity, hit_entity, joint::Joint::new(
-                    t * na::vec4(0.0, 0.0, 1.0, 0.0),
-                    world_to_local * world_space_hit,
-                ));
+                let grab_spring = world.get::<&Actor>(actor_entity).unwrap().grab_spring;
+                let joint = joint::GrabJoint::new(
+                    &world.get::<&physics::RigidBody>(actor_entity).unwrap(),
+                    &world.get::<&physics::RigidBody>(hit_entity).unwrap(),
+                    world_space_hit,
+                    grab_spring,
+                );
+                constraints.add_joint(actor_entity, hit_entity, joint);
 
                 world.query_one_mut::<&mut sprite_renderer::Sprite>(actor_entity).map(|x| x.tint = na::vec4(0.0, 1.0, 0.0, 1.0)).ok();
                 world.query_one_mut::<&mut draw_state::DrawState>(hit_entity).map(|x| x.hollow = true).ok();
@@ -55,18 +67,136 @@ pub fn update_actor(
                 world.query_one_mut::<&mut Actor>(actor_entity).map(|x| x.grab_state = GrabState::Miss).ok();
             }
         }
+        (true, GrabState::Hit(hit_entity)) => {
+            update_hold(constraints, world, input_state, actor_entity, hit_entity);
+        }
         (false, grab_state) => {
             world.query_one_mut::<&mut sprite_renderer::Sprite>(actor_entity).map(|x| x.tint = na::vec4(0.0, 0.0, 0.0, 1.0)).ok();
             world.query_one_mut::<&mut Actor>(actor_entity).map(|x| x.grab_state = GrabState::Not).ok();
             if let GrabState::Hit(hit_entity) = grab_state {
-                world.query_one_mut::<&mut draw_state::DrawState>(hit_entity).map(|x| x.hollow = false).ok();
-                constraints.remove_joint(actor_entity, hit_entity);
+                release(constraints, world, actor_entity, hit_entity);
+
+                // Throw the object forwards as it's let go
+                let (forward, throw_impulse) = world
+                    .query_one_mut::<(&physics::RigidBody, &Actor)>(actor_entity)
+                    .map(|(body, actor)| (body.orientation.to_matrix().column(2).normalize(), actor.throw_impulse))
+                    .unwrap();
+                world.query_one_mut::<&mut physics::RigidBody>(hit_entity).map(|x| x.velocity += x.inverse_mass * throw_impulse * forward).ok();
             }
         }
         _ => {}
     }
 }
 
+fn release(
+    constraints: &mut constraints::Constraints,
+    world: &mut hecs::World,
+    actor_entity: hecs::Entity,
+    hit_entity: hecs::Entity,
+) {
+    world
+        .query_one_mut::<&mut draw_state::DrawState>(hit_entity)
+        .map(|x| x.hollow = false)
+        .ok();
+    constraints.remove_joint(actor_entity, hit_entity);
+}
+
+// Move the held object nearer or further with the scroll wheel, and turn it with
+// the mouse while rotate_held is pressed. The planes of rotation are the same as
+// for looking around.
+fn update_hold(
+    constraints: &mut constraints::Constraints,
+    world: &mut hecs::World,
+    input_state: &input::InputState,
+    actor_entity: hecs::Entity,
+    hit_entity: hecs::Entity,
+) {
+    let body = *world.get::<&physics::RigidBody>(actor_entity).unwrap();
+    let joint = match constraints.get_joint::<joint::GrabJoint>(actor_entity, hit_entity) {
+        Some(joint) => joint,
+        None => return,
+    };
+
+    let (min, max) = HOLD_DISTANCE_RANGE;
+    joint.distance = (joint.distance + input_state.scroll * HOLD_SCROLL_STEP).clamp(min, max);
+
+    if input_state.rotate_held {
+        let (yaw_plane, pitch_plane, roll_plane) = look_planes(&body, input_state.hyperlook);
+        let mut rotation = input_state.yaw * yaw_plane + input_state.pitch * pitch_plane;
+        if input_state.roll_left {
+            rotation += 3.0 * roll_plane;
+        }
+        if input_state.roll_right {
+            rotation += -3.0 * roll_plane;
+        }
+        joint.rotate(HOLD_ROTATE_SPEED * rotation, &body);
+    }
+}
+
+// The planes in which the mouse and roll keys turn the view - the "hyperlook"
+// planes turn towards the ana direction instead
+fn look_planes(
+    body: &physics::RigidBody,
+    hyperlook: bool,
+) -> (ga::Bivector4, ga::Bivector4, ga::Bivector4) {
+    let orientation = body.orientation.to_matrix();
+    let right = orientation.column(0).normalize();
+    let up = orientation.column(1).normalize();
+    let forward = orientation.column(2).normalize();
+    let ana = orientation.column(3).normalize();
+    match hyperlook {
+        false => (forward.wedge(right), up.wedge(forward), right.wedge(up)),
+        true => (forward.wedge(ana), up.wedge(ana), right.wedge(ana)),
+    }
+}
+
+// Let go of a body which is about to be despawned
+pub fn body_despawned(world: &mut hecs::World, entity: hecs::Entity) {
+    for (_, (actor, sprite)) in
+        world.query_mut::<(&mut Actor, Option<&mut sprite_renderer::Sprite>)>()
+    {
+        if let GrabState::Hit(hit_entity) = actor.grab_state {
+            if hit_entity == entity {
+                actor.grab_state = GrabState::Miss;
+                if let Some(sprite) = sprite {
+                    sprite.tint = na::vec4(1.0, 0.0, 0.0, 1.0);
+                }
+            }
+        }
+    }
+}
+
+// Let go of the held object if the grab joint has broken. The grab stays in the
+// missed state until the button is released.
+pub fn joint_broken(
+    world: &mut hecs::World,
+    actor_entity: hecs::Entity,
+    event: &joint::JointBroken,
+) {
+    let grab_state = world
+        .get::<&Actor>(actor_entity)
+        .unwrap()
+        .grab_state
+        .clone();
+    match grab_state {
+        GrabState::Hit(hit_entity) if event.a == actor_entity && event.b == hit_entity => {
+            world
+                .query_one_mut::<&mut draw_state::DrawState>(hit_entity)
+                .map(|x| x.hollow = false)
+                .ok();
+            world
+                .query_one_mut::<&mut sprite_renderer::Sprite>(actor_entity)
+                .map(|x| x.tint = na::vec4(1.0, 0.0, 0.0, 1.0))
+                .ok();
+            world
+                .query_one_mut::<&mut Actor>(actor_entity)
+                .map(|x| x.grab_state = GrabState::Miss)
+                .ok();
+        }
+        _ => {}
+    }
+}
+
 fn update_movement(
     world: &mut hecs::World,
     input_state: &input::InputState,
@@ -109,10 +239,12 @@ fn update_movement(
         body.force -= ana * actor.move_thrust;
     }
 
-    let (yaw_plane, pitch_plane, roll_plane) = match input_state.hyperlook {
-        false => (forward.wedge(right), up.wedge(forward), right.wedge(up)),
-        true => (forward.wedge(ana), up.wedge(ana), right.wedge(ana)),
-    };
+    // The mouse and roll keys turn the held object instead
+    if input_state.rotate_held && matches!(actor.grab_state, GrabState::Hit(_)) {
+        return;
+    }
+
+    let (yaw_plane, pitch_plane, roll_plane) = look_planes(body, input_state.hyperlook);
 
     body.torque += actor.look_torque * input_state.yaw * yaw_plane;
     body.torque += actor.look_torque * input_state.pitch * pitch_plane;
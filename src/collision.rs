@@ -6,6 +6,7 @@ use crate::mesh;
 use crate::mpr;
 use crate::na;
 use crate::physics;
+use crate::raycast;
 
 use itertools::Itertools;
 use std::cmp::Ordering::Greater;
@@ -46,42 +47,114 @@ impl Collider {
     }
 }
 
+// Marks a collider as a trigger volume - it doesn't collide with anything, but
+// keeps track of which entities overlap it
+#[derive(Debug, Clone, Default)]
+pub struct Sensor {
+    pub overlapping: Vec<hecs::Entity>,
+}
+
 pub fn cast_ray(
     origin: na::Vector4,
     direction: na::Vector4,
     world: &hecs::World,
 ) -> Option<(hecs::Entity, f32)> {
+    pick(origin, direction, world).map(|(entity, t, _)| (entity, t))
+}
+
+// The nearest body along the ray, and how far along it is. Bodies with a
+// MeshIndex are hit exactly, also giving where on the mesh was hit in world
+// space, and the rest are hit on their convex hulls.
+pub fn pick(
+    origin: na::Vector4,
+    direction: na::Vector4,
+    world: &hecs::World,
+) -> Option<(hecs::Entity, f32, Option<raycast::Hit>)> {
     let mut nearest = None;
     let mut nearest_distance = f32::INFINITY;
-    for (entity, (body, collider)) in world.query::<(&physics::RigidBody, &Collider)>().iter() {
-        if let Some(t) = gjk::cast_ray(
-            &origin,
-            &direction,
-            collider,
-            &na::Affine4::from_po(body.position, body.orientation.to_matrix()),
-        ) {
-            if t < nearest_distance {
-                nearest = Some(entity);
-                nearest_distance = t;
+    for (entity, (body, collider, index)) in world
+        .query::<(
+            &physics::RigidBody,
+            &Collider,
+            Option<(&raycast::MeshIndex, &mesh::Mesh4)>,
+        )>()
+        .without::<&Sensor>()
+        .iter()
+    {
+        let transform = na::Affine4::from_po(body.position, body.orientation.to_matrix());
+        let (t, hit) = match index {
+            Some((index, mesh)) => {
+                let inverse = transform.inverse();
+                match index.cast_ray(mesh, inverse * origin, inverse.linear * direction) {
+                    Some(hit) => (
+                        hit.t,
+                        Some(raycast::Hit {
+                            position: transform * hit.position,
+                            normal: transform.linear * hit.normal,
+                            ..hit
+                        }),
+                    ),
+                    None => continue,
+                }
             }
+            None => match gjk::cast_ray(&origin, &direction, collider, &transform) {
+                Some(t) => (t, None),
+                None => continue,
+            },
+        };
+        if t < nearest_distance {
+            nearest = Some((entity, hit));
+            nearest_distance = t;
         }
     }
-    nearest.map(|entity| (entity, nearest_distance))
+    nearest.map(|(entity, hit)| (entity, nearest_distance, hit))
 }
 
-pub fn do_collisions(constraints: &mut constraints::Constraints, world: &mut hecs::World) {
+pub fn do_collisions(
+    settings: &physics::SolverSettings,
+    constraints: &mut constraints::Constraints,
+    world: &mut hecs::World,
+) {
+    for (_, sensor) in world.query_mut::<&mut Sensor>() {
+        sensor.overlapping.clear();
+    }
+    let mut overlaps = Vec::new();
+
     let mut draw_state_query = world.query::<&mut draw_state::DrawState>();
     for pair in world
-        .query::<(&Collider, &physics::RigidBody)>()
+        .query::<(
+            &Collider,
+            &physics::RigidBody,
+            Option<&contact::Material>,
+            Option<&Sensor>,
+        )>()
         .iter()
         .combinations(2)
     {
-        let (a, (a_collider, a_body)) = pair[0];
-        let (b, (b_collider, b_body)) = pair[1];
+        let (a, (a_collider, a_body, a_material, a_sensor)) = pair[0];
+        let (b, (b_collider, b_body, b_material, b_sensor)) = pair[1];
+
+        if a_sensor.is_some() || b_sensor.is_some() {
+            if a_sensor.is_some() != b_sensor.is_some()
+                && (a_body.position - b_body.position).norm()
+                    < (a_collider.radius + b_collider.radius)
+                && mpr::collide(
+                    a_collider,
+                    &a_body.get_transform(),
+                    b_collider,
+                    &b_body.get_transform(),
+                )
+                .is_some()
+            {
+                overlaps.push(if a_sensor.is_some() { (a, b) } else { (b, a) });
+            }
+            continue;
+        }
 
         let arbiter = constraints.get_arbiter(a, b);
-        let contact = if (a_body.position - b_body.position).norm()
-            < (a_collider.radius + b_collider.radius)
+        // Bodies which can't be moved by contacts can't collide with each other
+        let contact = if (a_body.is_dynamic() || b_body.is_dynamic())
+            && (a_body.position - b_body.position).norm() < (a_collider.radius + b_collider.radius)
         {
             mpr::collide(
                 a_collider,
@@ -96,7 +169,15 @@ pub fn do_collisions(constraints: &mut constraints::Constraints, world: &mut hec
         match (arbiter, contact) {
             (None, None) => {}
             (None, Some(contact_point)) => {
-                constraints.add_arbiter(a, b, contact::Arbiter::new(contact_point, a_body, b_body));
+                let material = contact::Material::mix(
+                    &a_material.copied().unwrap_or_default(),
+                    &b_material.copied().unwrap_or_default(),
+                );
+                constraints.add_arbiter(
+                    a,
+                    b,
+                    contact::Arbiter::new(material, contact_point, a_body, b_body),
+                );
                 if let Some(state) = draw_state_query.view().get_mut(a) {
                     state.contacts += 1;
                 }
@@ -105,7 +186,7 @@ pub fn do_collisions(constraints: &mut constraints::Constraints, world: &mut hec
                 }
             }
             (Some(arbiter), Some(contact_point)) => {
-                arbiter.update(contact_point, a_body, b_body);
+                arbiter.update(settings, contact_point, a_body, b_body);
             }
             (Some(_), None) => {
                 constraints.remove_arbiter(a, b);
@@ -120,4 +201,10 @@ pub fn do_collisions(constraints: &mut constraints::Constraints, world: &mut hec
             }
         }
     }
+
+    for (sensor, entity) in overlaps {
+        if let Ok(mut sensor) = world.get::<&mut Sensor>(sensor) {
+            sensor.overlapping.push(entity);
+        }
+    }
 }
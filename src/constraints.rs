@@ -3,8 +3,9 @@ use crate::joint;
 use crate::physics;
 
 pub struct Constraints {
-    joints: std::collections::HashMap<(hecs::Entity, hecs::Entity), joint::Joint>,
+    joints: std::collections::HashMap<(hecs::Entity, hecs::Entity), Box<dyn joint::Joint>>,
     arbiters: std::collections::HashMap<(hecs::Entity, hecs::Entity), contact::Arbiter>,
+    broken_joints: Vec<joint::JointBroken>,
 }
 
 impl Constraints {
@@ -12,22 +13,81 @@ impl Constraints {
         Self {
             joints: std::collections::HashMap::new(),
             arbiters: std::collections::HashMap::new(),
+            broken_joints: Vec::new(),
         }
     }
 
-    pub fn add_joint(
+    pub fn add_joint<J: joint::Joint + 'static>(
         &mut self,
         a: hecs::Entity,
         b: hecs::Entity,
-        joint: joint::Joint,
-    ) -> Option<joint::Joint> {
-        self.joints.insert((a, b), joint)
+        joint: J,
+    ) -> Option<Box<dyn joint::Joint>> {
+        self.joints.insert((a, b), Box::new(joint))
     }
 
-    pub fn remove_joint(&mut self, a: hecs::Entity, b: hecs::Entity) -> Option<joint::Joint> {
+    pub fn remove_joint(
+        &mut self,
+        a: hecs::Entity,
+        b: hecs::Entity,
+    ) -> Option<Box<dyn joint::Joint>> {
         self.joints.remove(&(a, b))
     }
 
+    // The joint between two bodies, if there is one of the given type
+    pub fn get_joint<J: joint::Joint + 'static>(
+        &mut self,
+        a: hecs::Entity,
+        b: hecs::Entity,
+    ) -> Option<&mut J> {
+        self.joints
+            .get_mut(&(a, b))
+            .and_then(|joint| joint.as_mut().as_any_mut().downcast_mut::<J>())
+    }
+
+    // Remove every joint and arbiter involving the entity, returning the other body
+    // of each arbiter
+    pub fn remove_body(&mut self, entity: hecs::Entity) -> Vec<hecs::Entity> {
+        self.joints.retain(|&(a, b), _| a != entity && b != entity);
+        let mut touching = Vec::new();
+        self.arbiters.retain(|&(a, b), _| {
+            if a == entity {
+                touching.push(b);
+            } else if b == entity {
+                touching.push(a);
+            }
+            a != entity && b != entity
+        });
+        touching
+    }
+
+    // Remove any joints which have failed, recording an event for each
+    pub fn break_joints(&mut self) {
+        let broken_joints = &mut self.broken_joints;
+        self.joints.retain(|&(a, b), joint| {
+            let broken = joint.is_broken();
+            if broken {
+                broken_joints.push(joint::JointBroken { a, b });
+            }
+            !broken
+        });
+    }
+
+    // Joints which have broken since this was last called
+    pub fn take_broken_joints(&mut self) -> Vec<joint::JointBroken> {
+        std::mem::take(&mut self.broken_joints)
+    }
+
+    // Bodies which are attached to a joint or touching another body, and so will
+    // be moved by the solver
+    pub fn constrained(&self) -> std::collections::HashSet<hecs::Entity> {
+        self.joints
+            .keys()
+            .chain(self.arbiters.keys())
+            .flat_map(|&(a, b)| [a, b])
+            .collect()
+    }
+
     pub fn add_arbiter(
         &mut self,
         a: hecs::Entity,
@@ -49,18 +109,25 @@ impl Constraints {
         self.arbiters.remove(&(a, b))
     }
 
-    pub fn prepare(&mut self, dt: f32, world: &mut hecs::World) {
+    pub fn arbiters(&self) -> impl Iterator<Item = &contact::Arbiter> {
+        self.arbiters.values()
+    }
+
+    pub fn prepare(
+        &mut self,
+        dt: f32,
+        settings: &physics::SolverSettings,
+        world: &mut hecs::World,
+    ) {
         let mut body_query = world.query_mut::<&mut physics::RigidBody>();
         let mut body_view = body_query.view();
         for ((a, b), joint) in self.joints.iter_mut() {
             let [a_body, b_body] = body_view.get_mut_n([*a, *b]).map(|x| x.unwrap());
-            joint.prepare(dt, a_body, b_body);
+            joint.prepare(dt, settings, a_body, b_body);
         }
         for ((a, b), arbiter) in self.arbiters.iter_mut() {
             let [a_body, b_body] = body_view.get_mut_n([*a, *b]).map(|x| x.unwrap());
-            for contact in arbiter.contacts.iter_mut() {
-                contact.prepare(dt, a_body, b_body);
-            }
+            arbiter.prepare(dt, settings, a_body, b_body);
         }
     }
 
@@ -73,9 +140,20 @@ impl Constraints {
         }
         for ((a, b), arbiter) in self.arbiters.iter_mut() {
             let [a_body, b_body] = body_view.get_mut_n([*a, *b]).map(|x| x.unwrap());
-            for contact in arbiter.contacts.iter_mut() {
-                contact.apply(a_body, b_body);
-            }
+            arbiter.apply(a_body, b_body);
+        }
+    }
+
+    pub fn apply_position(&mut self, world: &mut hecs::World) {
+        let mut body_query = world.query_mut::<&mut physics::RigidBody>();
+        let mut body_view = body_query.view();
+        for ((a, b), joint) in self.joints.iter_mut() {
+            let [a_body, b_body] = body_view.get_mut_n([*a, *b]).map(|x| x.unwrap());
+            joint.apply_position(a_body, b_body);
+        }
+        for ((a, b), arbiter) in self.arbiters.iter_mut() {
+            let [a_body, b_body] = body_view.get_mut_n([*a, *b]).map(|x| x.unwrap());
+            arbiter.apply_position(a_body, b_body);
         }
     }
 }
@@ -4,6 +4,40 @@ use crate::physics;
 
 use crate::ga::Wedge;
 
+// Surface properties of a body. Bodies without one use the default.
+#[derive(Debug, Copy, Clone)]
+pub struct Material {
+    pub friction: f32,
+    pub restitution: f32,
+    // Resistance to spinning in the planes perpendicular to the contact normal
+    pub torsional_friction: f32,
+    // Resistance to rotating in the planes containing the contact normal
+    pub rolling_friction: f32,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            friction: 0.3,
+            restitution: 0.1,
+            torsional_friction: 0.0,
+            rolling_friction: 0.0,
+        }
+    }
+}
+
+impl Material {
+    // The material used for a contact between two bodies
+    pub fn mix(a: &Material, b: &Material) -> Material {
+        Material {
+            friction: (a.friction * b.friction).sqrt(),
+            restitution: a.restitution.max(b.restitution),
+            torsional_friction: a.torsional_friction.max(b.torsional_friction),
+            rolling_friction: a.rolling_friction.max(b.rolling_friction),
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone, Default)]
 pub struct ContactPoint {
     pub a_local: na::Vector4,
@@ -30,9 +64,17 @@ pub struct Contact {
 
     impulse: na::Vector4,
     effective_masses: na::Vector4,
+
+    position_bias: f32,
+    pseudo_impulse: f32,
 }
 
 impl Contact {
+    // How far the bodies overlap at this contact
+    pub fn depth(&self) -> f32 {
+        self.depth
+    }
+
     pub fn from_contact_point(
         contact_point: ContactPoint,
         a_body: &physics::RigidBody,
@@ -64,6 +106,9 @@ impl Contact {
 
             impulse: na::Vector4::default(),
             effective_masses: na::Vector4::default(),
+
+            position_bias: 0.0,
+            pseudo_impulse: 0.0,
         };
         new.update(true, a_body, b_body);
         new
@@ -103,14 +148,11 @@ impl Contact {
     pub fn prepare(
         &mut self,
         dt: f32,
+        settings: &physics::SolverSettings,
+        material: &Material,
         a_body: &mut physics::RigidBody,
         b_body: &mut physics::RigidBody,
     ) {
-        const RESTITUTION: f32 = 0.1;
-        const BIAS_FACTOR: f32 = 0.1;
-        const DEPTH_SLOP: f32 = 0.001;
-        const REBOUND_SLOP: f32 = 0.1;
-
         let a_jacobian =
             self.basis.transpose() * ga::Bivector4::dot_vector_matrix(self.a_world_space_anchor);
         let b_jacobian =
@@ -130,9 +172,24 @@ impl Contact {
         let velocity = (a_body.velocity - b_body.velocity)
             + (a_body.angular_velocity.dot(&self.a_world_space_anchor)
                 - b_body.angular_velocity.dot(&self.b_world_space_anchor));
-        self.bias = ((BIAS_FACTOR * (self.depth - DEPTH_SLOP).max(0.0)) / dt)
-            + RESTITUTION * (self.basis.column(0).dot(&velocity) - REBOUND_SLOP).max(0.0);
+        let penetration_bias =
+            (settings.contact_bias_factor * (self.depth - settings.depth_slop).max(0.0)) / dt;
+        self.bias = material.restitution
+            * (self.basis.column(0).dot(&velocity) - settings.rebound_slop).max(0.0);
+        match settings.position_correction {
+            physics::PositionCorrection::Baumgarte => {
+                self.bias += penetration_bias;
+                self.position_bias = 0.0;
+            }
+            physics::PositionCorrection::SplitImpulse => {
+                self.position_bias = penetration_bias;
+            }
+        }
+        self.pseudo_impulse = 0.0;
 
+        if !settings.warm_starting {
+            self.impulse = na::Vector4::zeros();
+        }
         let impulse_world = self.basis * self.impulse;
         a_body.velocity += impulse_world * a_body.inverse_mass;
         a_body.angular_velocity +=
@@ -142,7 +199,12 @@ impl Contact {
             b_body.inverse_inertia_tensor * self.b_world_space_anchor.wedge(impulse_world);
     }
 
-    pub fn apply(&mut self, a_body: &mut physics::RigidBody, b_body: &mut physics::RigidBody) {
+    pub fn apply(
+        &mut self,
+        material: &Material,
+        a_body: &mut physics::RigidBody,
+        b_body: &mut physics::RigidBody,
+    ) {
         let velocity = a_body.velocity - b_body.velocity
             + a_body.angular_velocity.dot(&self.a_world_space_anchor)
             - b_body.angular_velocity.dot(&self.b_world_space_anchor);
@@ -156,7 +218,7 @@ impl Contact {
         let impulse_original = self.impulse;
         // cap or clamp each axis as appropriate
         self.impulse[0] = (delta_impulse[0] + self.impulse[0]).max(0.0);
-        let max_tangential_impulse = 0.3 * self.impulse[0];
+        let max_tangential_impulse = material.friction * self.impulse[0];
         for i in 1..4 {
             self.impulse[i] = (delta_impulse[i] + self.impulse[i])
                 .clamp(-max_tangential_impulse, max_tangential_impulse);
@@ -172,14 +234,52 @@ impl Contact {
         b_body.angular_velocity -=
             b_body.inverse_inertia_tensor * self.b_world_space_anchor.wedge(delta_impulse);
     }
+
+    // Push the bodies apart along the normal using only the pseudo-velocities, so
+    // that penetration is resolved without adding any real momentum
+    pub fn apply_position(
+        &mut self,
+        a_body: &mut physics::RigidBody,
+        b_body: &mut physics::RigidBody,
+    ) {
+        let normal = self.basis.column(0);
+        let velocity = a_body.pseudo_velocity - b_body.pseudo_velocity
+            + a_body
+                .pseudo_angular_velocity
+                .dot(&self.a_world_space_anchor)
+            - b_body
+                .pseudo_angular_velocity
+                .dot(&self.b_world_space_anchor);
+
+        let delta_impulse = self.effective_masses[0] * (self.position_bias - normal.dot(&velocity));
+        let pseudo_impulse_original = self.pseudo_impulse;
+        self.pseudo_impulse = (self.pseudo_impulse + delta_impulse).max(0.0);
+        let delta_impulse = normal * (self.pseudo_impulse - pseudo_impulse_original);
+
+        a_body.pseudo_velocity += delta_impulse * a_body.inverse_mass;
+        a_body.pseudo_angular_velocity +=
+            a_body.inverse_inertia_tensor * self.a_world_space_anchor.wedge(delta_impulse);
+        b_body.pseudo_velocity -= delta_impulse * b_body.inverse_mass;
+        b_body.pseudo_angular_velocity -=
+            b_body.inverse_inertia_tensor * self.b_world_space_anchor.wedge(delta_impulse);
+    }
 }
 
 pub struct Arbiter {
     pub contacts: std::vec::Vec<Contact>,
+    material: Material,
+
+    // Unit bivectors for the planes of rotation resisted by torsional friction
+    // (the first three columns, perpendicular to the normal) and rolling friction
+    // (the last three, containing the normal)
+    angular_basis: na::Matrix6,
+    angular_impulse: na::Vector6,
+    angular_effective_masses: na::Vector6,
 }
 
 impl Arbiter {
     pub fn new(
+        material: Material,
         contact_point: ContactPoint,
         a_body: &physics::RigidBody,
         b_body: &physics::RigidBody,
@@ -187,12 +287,119 @@ impl Arbiter {
         let contact = Contact::from_contact_point(contact_point, a_body, b_body);
         Self {
             contacts: vec![contact],
+            material,
+
+            angular_basis: na::Matrix6::zeros(),
+            angular_impulse: na::Vector6::zeros(),
+            angular_effective_masses: na::Vector6::zeros(),
+        }
+    }
+
+    pub fn prepare(
+        &mut self,
+        dt: f32,
+        settings: &physics::SolverSettings,
+        a_body: &mut physics::RigidBody,
+        b_body: &mut physics::RigidBody,
+    ) {
+        for contact in self.contacts.iter_mut() {
+            contact.prepare(dt, settings, &self.material, a_body, b_body);
+        }
+
+        if self.material.torsional_friction == 0.0 && self.material.rolling_friction == 0.0 {
+            return;
+        }
+
+        // All the contacts in an arbiter share (roughly) the same normal, so the
+        // planes are built from the basis of the first one. With no contacts left
+        // there's nothing for friction to push against.
+        let Some(first) = self.contacts.first() else {
+            self.angular_impulse = na::Vector6::zeros();
+            return;
+        };
+        let basis = first.basis;
+        let (n, t1, t2, t3) = (
+            basis.column(0).into_owned(),
+            basis.column(1).into_owned(),
+            basis.column(2).into_owned(),
+            basis.column(3).into_owned(),
+        );
+        self.angular_basis = na::Matrix6::from_columns(&[
+            t1.wedge(t2).as_vector(),
+            t1.wedge(t3).as_vector(),
+            t2.wedge(t3).as_vector(),
+            n.wedge(t1).as_vector(),
+            n.wedge(t2).as_vector(),
+            n.wedge(t3).as_vector(),
+        ]);
+
+        // Each plane is treated separately, like the axes of the contacts
+        let inverse_inertia_tensor = a_body.inverse_inertia_tensor + b_body.inverse_inertia_tensor;
+        for i in 0..6 {
+            let plane = self.angular_basis.column(i);
+            let k = plane.dot(&(inverse_inertia_tensor * plane));
+            self.angular_effective_masses[i] = if k > 0.0 { 1.0 / k } else { 0.0 };
+        }
+
+        if !settings.warm_starting {
+            self.angular_impulse = na::Vector6::zeros();
+        }
+        let impulse_world = ga::Bivector4::from_vector(self.angular_basis * self.angular_impulse);
+        a_body.angular_velocity += a_body.inverse_inertia_tensor * impulse_world;
+        b_body.angular_velocity -= b_body.inverse_inertia_tensor * impulse_world;
+    }
+
+    pub fn apply(&mut self, a_body: &mut physics::RigidBody, b_body: &mut physics::RigidBody) {
+        for contact in self.contacts.iter_mut() {
+            contact.apply(&self.material, a_body, b_body);
+        }
+
+        if self.material.torsional_friction == 0.0 && self.material.rolling_friction == 0.0 {
+            return;
+        }
+
+        let angular_velocity = (a_body.angular_velocity - b_body.angular_velocity).as_vector();
+        let mut delta_impulse = self
+            .angular_effective_masses
+            .component_mul(&(self.angular_basis.transpose() * -angular_velocity));
+
+        // Like tangential friction, the angular impulses are capped by the total
+        // normal impulse holding the bodies together
+        let normal_impulse: f32 = self.contacts.iter().map(|x| x.impulse[0]).sum();
+        let max_torsional_impulse = self.material.torsional_friction * normal_impulse;
+        let max_rolling_impulse = self.material.rolling_friction * normal_impulse;
+
+        let impulse_original = self.angular_impulse;
+        for i in 0..6 {
+            let max_impulse = if i < 3 {
+                max_torsional_impulse
+            } else {
+                max_rolling_impulse
+            };
+            self.angular_impulse[i] =
+                (self.angular_impulse[i] + delta_impulse[i]).clamp(-max_impulse, max_impulse);
+        }
+        delta_impulse = self.angular_basis * (self.angular_impulse - impulse_original);
+
+        let delta_impulse = ga::Bivector4::from_vector(delta_impulse);
+        a_body.angular_velocity += a_body.inverse_inertia_tensor * delta_impulse;
+        b_body.angular_velocity -= b_body.inverse_inertia_tensor * delta_impulse;
+    }
+
+    pub fn apply_position(
+        &mut self,
+        a_body: &mut physics::RigidBody,
+        b_body: &mut physics::RigidBody,
+    ) {
+        for contact in self.contacts.iter_mut() {
+            contact.apply_position(a_body, b_body);
         }
     }
 
     // This whole algorithm seems sketchy as hell, but it works.
     pub fn update(
         &mut self,
+        settings: &physics::SolverSettings,
         contact_point: ContactPoint,
         a_body: &physics::RigidBody,
         b_body: &physics::RigidBody,
@@ -215,11 +422,12 @@ impl Arbiter {
         self.contacts.retain(|x| x.valid);
 
         // Only keep the new contact if it's not too close to an existing one
-        if new_distance > 0.0001 {
+        if new_distance > settings.contact_merge_distance.powi(2) {
             self.contacts.push(new_contact);
         }
 
-        if self.contacts.len() < 6 {
+        // Pruning always leaves at least one contact, however low max_contacts is
+        if self.contacts.len() < settings.max_contacts.max(2) {
             return;
         }
         // If we've reached max contacts, we need to prune one of them. Find the
@@ -0,0 +1,1015 @@
+use crate::ga::Wedge;
+use crate::mesh;
+use crate::na;
+
+use std::collections::HashMap;
+
+// Boolean operations on boundary meshes, with binary space partitioning trees
+// built from the meshes' cells - the same as csg.js, one dimension up. Each
+// tree splits space by the hyperplane of one of its cells, and cells are cut
+// where they cross a hyperplane. Both meshes need to be closed and in the same
+// space, so transform them into place first.
+//
+// While they're being cut up, cells are kept as convex polyhedra rather than
+// tetrahedra, so that a cell crossed by many hyperplanes stays as one piece on
+// each side rather than shattering. They're only split back into tetrahedra at
+// the end, after their faces are split to match up with their neighbours', so
+// that the result is closed, with no T-junctions.
+impl mesh::Mesh4 {
+    pub fn union(&self, other: &Self) -> Self {
+        let mut a = Bsp::new(cells(self));
+        let mut b = Bsp::new(cells(other));
+        a.clip_to(&b);
+        b.clip_to(&a);
+        b.invert();
+        b.clip_to(&a);
+        b.invert();
+        a.build(0, b.all_cells());
+        mesh(a.all_cells())
+    }
+
+    pub fn difference(&self, other: &Self) -> Self {
+        let mut a = Bsp::new(cells(self));
+        let mut b = Bsp::new(cells(other));
+        a.invert();
+        a.clip_to(&b);
+        b.clip_to(&a);
+        b.invert();
+        b.clip_to(&a);
+        b.invert();
+        a.build(0, b.all_cells());
+        a.invert();
+        mesh(a.all_cells())
+    }
+
+    pub fn intersection(&self, other: &Self) -> Self {
+        let mut a = Bsp::new(cells(self));
+        let mut b = Bsp::new(cells(other));
+        a.invert();
+        b.clip_to(&a);
+        b.invert();
+        a.clip_to(&b);
+        b.clip_to(&a);
+        a.build(0, b.all_cells());
+        a.invert();
+        mesh(a.all_cells())
+    }
+}
+
+// How far from a hyperplane a point can be and still count as on it
+const EPSILON: f32 = 1e-5;
+
+#[derive(Debug, Clone, Copy)]
+struct Vertex {
+    position: na::Vector4,
+    normal: na::Vector4,
+    color: na::Vector4,
+    material: na::Vector4,
+}
+
+impl Vertex {
+    fn weighted(weights: impl IntoIterator<Item = (Vertex, f32)>) -> Vertex {
+        weights.into_iter().fold(
+            Vertex {
+                position: na::Vector4::zeros(),
+                normal: na::Vector4::zeros(),
+                color: na::Vector4::zeros(),
+                material: na::Vector4::zeros(),
+            },
+            |sum, (x, weight)| Vertex {
+                position: sum.position + x.position * weight,
+                normal: sum.normal + x.normal * weight,
+                color: sum.color + x.color * weight,
+                material: sum.material + x.material * weight,
+            },
+        )
+    }
+
+    fn lerp(&self, other: &Vertex, t: f32) -> Vertex {
+        Vertex {
+            position: self.position.lerp(&other.position, t),
+            normal: self.normal.lerp(&other.normal, t),
+            color: self.color.lerp(&other.color, t),
+            material: self.material.lerp(&other.material, t),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Plane {
+    normal: na::Vector4,
+    offset: f32,
+}
+
+impl Plane {
+    fn new(normal: na::Vector4, point: na::Vector4) -> Self {
+        let normal = normal.normalize();
+        Self {
+            normal,
+            offset: normal.dot(&point),
+        }
+    }
+
+    fn distance(&self, point: na::Vector4) -> f32 {
+        self.normal.dot(&point) - self.offset
+    }
+
+    fn flip(&mut self) {
+        self.normal = -self.normal;
+        self.offset = -self.offset;
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Corner {
+    vertex: Vertex,
+    // Which of the cell's faces the corner is on
+    faces: Vec<usize>,
+}
+
+// A convex polyhedron, lying in a hyperplane
+#[derive(Debug, Clone)]
+struct Cell {
+    corners: Vec<Corner>,
+    // Hyperplanes which cut the cell's hyperplane along each of its faces. Only
+    // which corners are on them matters, not which way they face.
+    faces: Vec<Plane>,
+    // The hyperplane the cell lies in, facing out of the solid
+    plane: Plane,
+    // Which cell this was cut from, while clipping
+    source: usize,
+}
+
+impl Cell {
+    fn tetrahedron(vertices: [Vertex; 4], plane: Plane) -> Self {
+        let positions = vertices.map(|x| x.position);
+        let faces = [0, 1, 2, 3].map(|i| {
+            let [a, b, c] = [1, 2, 3].map(|j| positions[(i + j) % 4]);
+            Plane::new((b - a).wedge((c - a).wedge(plane.normal)).normal(), a)
+        });
+        Self {
+            corners: (0..4)
+                .map(|i| Corner {
+                    vertex: vertices[i],
+                    faces: (0..4).filter(|&j| j != i).collect(),
+                })
+                .collect(),
+            faces: faces.to_vec(),
+            plane,
+            source: 0,
+        }
+    }
+
+    fn flip(&mut self) {
+        for corner in self.corners.iter_mut() {
+            corner.vertex.normal = -corner.vertex.normal;
+        }
+        self.plane.flip();
+    }
+
+    // Cut the cell in two along the hyperplane. New corners are made where it
+    // crosses an edge, which is between two corners sharing two faces.
+    fn cut(&self, plane: &Plane, distances: &[f32]) -> (Cell, Cell) {
+        let face = self.faces.len();
+        let mut faces = self.faces.clone();
+        faces.push(*plane);
+        let mut front = Vec::new();
+        let mut back = Vec::new();
+        for (corner, &distance) in self.corners.iter().zip(distances) {
+            let mut corner = corner.clone();
+            if distance.abs() <= EPSILON {
+                corner.faces.push(face);
+            }
+            if distance >= -EPSILON {
+                front.push(corner.clone());
+            }
+            if distance <= EPSILON {
+                back.push(corner);
+            }
+        }
+        for (a, &a_distance) in self.corners.iter().zip(distances) {
+            for (b, &b_distance) in self.corners.iter().zip(distances) {
+                if a_distance <= EPSILON || b_distance >= -EPSILON {
+                    continue;
+                }
+                let mut shared: Vec<usize> = a
+                    .faces
+                    .iter()
+                    .filter(|x| b.faces.contains(x))
+                    .copied()
+                    .collect();
+                if shared.len() < 2 {
+                    continue;
+                }
+                shared.push(face);
+                let t = a_distance / (a_distance - b_distance);
+                let corner = Corner {
+                    vertex: a.vertex.lerp(&b.vertex, t),
+                    faces: shared,
+                };
+                front.push(corner.clone());
+                back.push(corner);
+            }
+        }
+        let piece = |corners| Cell {
+            corners,
+            faces: faces.clone(),
+            plane: self.plane,
+            source: self.source,
+        };
+        (piece(front), piece(back))
+    }
+
+    // The corners on each face, in order around it, as indices into corners,
+    // along with the face's plane. Corners welded into the same point only count
+    // once, and faces which are left with no width are skipped.
+    fn polygons(&self, points: &[usize], positions: &[na::Vector4]) -> Vec<(&Plane, Vec<usize>)> {
+        let position = |i: usize| positions[points[i]];
+        self.faces
+            .iter()
+            .enumerate()
+            .filter_map(|(face_index, face)| {
+                let mut on: Vec<usize> = (0..self.corners.len())
+                    .filter(|&i| self.corners[i].faces.contains(&face_index))
+                    .collect();
+                on.sort_by_key(|&i| points[i]);
+                on.dedup_by_key(|i| points[*i]);
+                if on.len() < 3 {
+                    return None;
+                }
+                let centre = on.iter().map(|&i| position(i)).sum::<na::Vector4>() / on.len() as f32;
+                let u = (position(on[0]) - centre).try_normalize(0.0)?;
+                let v = u.wedge(self.plane.normal.wedge(face.normal)).normal();
+                let angle = |i: usize| {
+                    let offset = position(i) - centre;
+                    offset.dot(&v).atan2(offset.dot(&u))
+                };
+                on.sort_by(|&a, &b| angle(a).total_cmp(&angle(b)));
+                let welded: Vec<usize> = on.iter().map(|&i| points[i]).collect();
+                (width(&welded, positions) > EPSILON).then_some((face, on))
+            })
+            .collect()
+    }
+}
+
+// Meshes aren't always wound consistently, so each cell is turned to face the
+// same way as its vertex normals
+fn cells(mesh: &mesh::Mesh4) -> Vec<Cell> {
+    mesh.indices
+        .chunks(4)
+        .filter_map(|tetrahedron| {
+            let vertices = [0, 1, 2, 3].map(|i| {
+                let i = tetrahedron[i] as usize;
+                Vertex {
+                    position: mesh.vertices[i],
+                    normal: mesh.normals[i],
+                    color: mesh.colors[i],
+                    material: mesh.materials[i],
+                }
+            });
+            let mut normal = mesh::tetrahedron_normal(&vertices.map(|x| x.position));
+            if normal.norm() < 1e-12 {
+                return None;
+            }
+            if normal.dot(&vertices.iter().map(|x| x.normal).sum()) < 0.0 {
+                normal = -normal;
+            }
+            Some(Cell::tetrahedron(
+                vertices,
+                Plane::new(normal, vertices[0].position),
+            ))
+        })
+        .collect()
+}
+
+// Cells are cut separately, so a corner made by cutting one can land on an edge
+// or face of a neighbour which wasn't cut, leaving a T-junction. So before
+// they're split into tetrahedra, the corners are welded, cells left flat by
+// cuts close to a corner are dropped, and each face is split into the pieces
+// where it overlaps faces of neighbouring cells. Both cells share each piece,
+// and it's fanned into triangles the same way for both, with any corners on its
+// edges included. Each cell is then coned from its middle to the triangles of
+// its faces.
+fn mesh(cells: Vec<Cell>) -> mesh::Mesh4 {
+    let mut points = Points::default();
+    let mut faces = Vec::new();
+    let mut kept = Vec::new();
+    for cell in cells.iter() {
+        let ids: Vec<usize> = cell
+            .corners
+            .iter()
+            .map(|x| points.weld(x.vertex.position))
+            .collect();
+        let polygons = cell.polygons(&ids, &points.positions);
+        let face_corners =
+            |polygon: &Vec<usize>| polygon.iter().map(|&i| ids[i]).collect::<Vec<_>>();
+        if is_flat(
+            &polygons
+                .iter()
+                .map(|x| face_corners(&x.1))
+                .collect::<Vec<_>>(),
+            &points.positions,
+        ) {
+            continue;
+        }
+        for (plane, polygon) in polygons.iter() {
+            // At right angles to both the cell's hyperplane and the face's plane
+            let e = plane.normal - cell.plane.normal * cell.plane.normal.dot(&plane.normal);
+            let Some(e) = e.try_normalize(EPSILON) else {
+                continue;
+            };
+            faces.push(Face {
+                cell: kept.len(),
+                across: [cell.plane.normal, e],
+                corners: face_corners(polygon),
+                vertices: polygon
+                    .iter()
+                    .map(|&i| Vertex {
+                        position: points.positions[ids[i]],
+                        ..cell.corners[i].vertex
+                    })
+                    .collect(),
+                pieces: Vec::new(),
+            });
+        }
+        kept.push(cell);
+    }
+
+    let mut pieces = overlaps(&mut faces, &mut points);
+    for face in faces.iter_mut() {
+        if face.pieces.is_empty() {
+            face.pieces.push(pieces.len());
+            pieces.push(face.corners.clone());
+        }
+    }
+    let triangles = triangulate(&pieces, &mut points);
+
+    let mut tetrahedra: Vec<[Vertex; 4]> = Vec::new();
+    let mut cell_faces = vec![Vec::new(); kept.len()];
+    for face in faces.iter() {
+        cell_faces[face.cell].push(face);
+    }
+    for (cell, faces) in kept.iter().zip(cell_faces) {
+        let start = tetrahedra.len();
+        let middle = Vertex::weighted(
+            cell.corners
+                .iter()
+                .map(|x| (x.vertex, 1.0 / cell.corners.len() as f32)),
+        );
+        // Tetrahedra which weren't split up are kept as they are
+        let mut corners: Vec<(usize, Vertex)> = faces
+            .iter()
+            .flat_map(|x| x.corners.iter().copied().zip(x.vertices.iter().copied()))
+            .collect();
+        corners.sort_by_key(|x| x.0);
+        corners.dedup_by_key(|x| x.0);
+        let unchanged = faces
+            .iter()
+            .all(|x| x.pieces.len() == 1 && triangles[x.pieces[0]].len() == 1);
+        if let ([a, b, c, d], true) = (&corners[..], unchanged) {
+            tetrahedra.push([a.1, b.1, c.1, d.1]);
+        } else {
+            for face in faces.iter() {
+                for &piece in face.pieces.iter() {
+                    for triangle in triangles[piece].iter() {
+                        let [a, b, c] = triangle.map(|x| face.interpolate(points.positions[x]));
+                        tetrahedra.push([middle, a, b, c]);
+                    }
+                }
+            }
+        }
+        // Wind them all to face the same way as the cell
+        for tetrahedron in tetrahedra[start..].iter_mut() {
+            let normal = mesh::tetrahedron_normal(&tetrahedron.map(|x| x.position));
+            if normal.dot(&cell.plane.normal) < 0.0 {
+                tetrahedron.swap(2, 3);
+            }
+        }
+    }
+
+    let vertices = tetrahedra.iter().flatten();
+    mesh::Mesh4 {
+        materials: vertices.clone().map(|x| x.material).collect(),
+        ..mesh::Mesh4::new(
+            vertices.clone().map(|x| x.position).collect(),
+            vertices.clone().map(|x| x.normal).collect(),
+            vertices.map(|x| x.color).collect(),
+            (0..tetrahedra.len() as u32 * 4).collect(),
+        )
+    }
+}
+
+// Corners closer than this in every coordinate are welded together. It's more
+// than mesh::weld's tolerance, so that corners kept apart here stay apart there,
+// and enough to catch corners made by hyperplanes that are nearly parallel,
+// which land a little way apart where they should meet.
+const WELD: f32 = 10.0 * EPSILON;
+
+// The welded corners of the result, in a grid of WELD-sized boxes for finding
+// the ones nearby
+#[derive(Default)]
+struct Points {
+    positions: Vec<na::Vector4>,
+    grid: HashMap<[i64; 4], Vec<usize>>,
+}
+
+impl Points {
+    fn weld(&mut self, position: na::Vector4) -> usize {
+        let key = [0, 1, 2, 3].map(|i| (position[i] / WELD).floor() as i64);
+        for offset in 0..81 {
+            let neighbour = [0, 1, 2, 3].map(|i| key[i] + (offset / 3_i64.pow(i as u32)) % 3 - 1);
+            let found = self.grid.get(&neighbour).and_then(|points| {
+                points
+                    .iter()
+                    .find(|&&x| (self.positions[x] - position).amax() <= WELD)
+            });
+            if let Some(&point) = found {
+                return point;
+            }
+        }
+        self.positions.push(position);
+        self.grid
+            .entry(key)
+            .or_default()
+            .push(self.positions.len() - 1);
+        self.positions.len() - 1
+    }
+}
+
+// A face of one of the cells of the result
+struct Face {
+    cell: usize,
+    // Unit normals to the plane the face is in, from the planes that make it,
+    // which are better than its corners for telling apart thin faces
+    across: [na::Vector4; 2],
+    // Welded points, in order around the face
+    corners: Vec<usize>,
+    vertices: Vec<Vertex>,
+    // Indices of the pieces it's split into
+    pieces: Vec<usize>,
+}
+
+impl Face {
+    // A vertex somewhere on the face, from the nearest triangle of a fan of its
+    // corners
+    fn interpolate(&self, position: na::Vector4) -> Vertex {
+        let a = self.vertices[0];
+        (1..self.vertices.len() - 1)
+            .filter_map(|i| {
+                let (b, c) = (self.vertices[i], self.vertices[i + 1]);
+                let (s, t) = plane_coordinates(
+                    a.position,
+                    b.position - a.position,
+                    c.position - a.position,
+                    position,
+                )?;
+                Some((
+                    [(a, 1.0 - s - t), (b, s), (c, t)],
+                    (1.0 - s - t).min(s).min(t),
+                ))
+            })
+            .max_by(|x, y| x.1.total_cmp(&y.1))
+            .map_or(Vertex { position, ..a }, |(weights, _)| Vertex {
+                position,
+                ..Vertex::weighted(weights)
+            })
+    }
+}
+
+fn centre(points: &[usize], positions: &[na::Vector4]) -> na::Vector4 {
+    points.iter().map(|&x| positions[x]).sum::<na::Vector4>() / points.len() as f32
+}
+
+// Twice the area of a polygon, over its perimeter, which for a long thin one is
+// about its width
+fn width(polygon: &[usize], positions: &[na::Vector4]) -> f32 {
+    let centre = centre(polygon, positions);
+    let mut area = 0.0;
+    let mut perimeter = 0.0;
+    for i in 0..polygon.len() {
+        let (a, b) = (
+            positions[polygon[i]],
+            positions[polygon[(i + 1) % polygon.len()]],
+        );
+        area += triangle_area(centre, a, b);
+        perimeter += (b - a).norm();
+    }
+    2.0 * area / perimeter.max(f32::MIN_POSITIVE)
+}
+
+// Whether the cell, given by its faces, is thinner than EPSILON, from its
+// volume over its surface area
+fn is_flat(faces: &[Vec<usize>], positions: &[na::Vector4]) -> bool {
+    if faces.len() < 4 {
+        return true;
+    }
+    let middle = centre(&faces.concat(), positions);
+    let mut volume = 0.0;
+    let mut area = 0.0;
+    for face in faces {
+        let a = positions[face[0]];
+        for i in 1..face.len() - 1 {
+            let (b, c) = (positions[face[i]], positions[face[i + 1]]);
+            volume += mesh::tetrahedron_normal(&[middle, a, b, c]).norm() / 6.0;
+            area += triangle_area(a, b, c);
+        }
+    }
+    3.0 * volume / area.max(f32::MIN_POSITIVE) <= EPSILON
+}
+
+fn triangle_area(a: na::Vector4, b: na::Vector4, c: na::Vector4) -> f32 {
+    (b - a).wedge(c - a).norm() / 2.0
+}
+
+// The point as origin + s * a + t * b, as near as it gets. None if the edges
+// are parallel.
+fn plane_coordinates(
+    origin: na::Vector4,
+    a: na::Vector4,
+    b: na::Vector4,
+    point: na::Vector4,
+) -> Option<(f32, f32)> {
+    let u = a.try_normalize(0.0)?;
+    let v = (b - u * u.dot(&b)).try_normalize(EPSILON * b.norm())?;
+    let offset = point - origin;
+    let (x, y) = (offset.dot(&u), offset.dot(&v));
+    let t = y / b.dot(&v);
+    let s = (x - t * b.dot(&u)) / a.norm();
+    Some((s, t))
+}
+
+// Splits the faces where they overlap faces of other cells in the same plane,
+// into the polygons where each pair overlaps, and returns those pieces. Faces
+// are swept through in order along x, to only compare those whose bounds
+// overlap.
+fn overlaps(faces: &mut [Face], points: &mut Points) -> Vec<Vec<usize>> {
+    let bounds: Vec<(na::Vector4, na::Vector4)> = faces
+        .iter()
+        .map(|face| {
+            face.corners.iter().fold(
+                (
+                    na::Vector4::repeat(f32::INFINITY),
+                    na::Vector4::repeat(-f32::INFINITY),
+                ),
+                |(min, max), &x| (min.inf(&points.positions[x]), max.sup(&points.positions[x])),
+            )
+        })
+        .collect();
+    let mut order: Vec<usize> = (0..faces.len()).collect();
+    order.sort_by(|&a, &b| bounds[a].0[0].total_cmp(&bounds[b].0[0]));
+
+    let mut pieces = Vec::new();
+    for (i, &a) in order.iter().enumerate() {
+        for &b in order[i + 1..].iter() {
+            let ((a_min, a_max), (b_min, b_max)) = (bounds[a], bounds[b]);
+            if b_min[0] > a_max[0] + EPSILON {
+                break;
+            }
+            if faces[a].cell == faces[b].cell
+                || (0..4).any(|i| b_min[i] > a_max[i] + EPSILON || a_min[i] > b_max[i] + EPSILON)
+            {
+                continue;
+            }
+            let positions = |face: &Face| -> Vec<na::Vector4> {
+                face.corners.iter().map(|&x| points.positions[x]).collect()
+            };
+            // Each face has to be in the other's plane, which a thin face can
+            // be for two faces meeting at an edge, but not the other way round
+            let on = |face: &Face, other: &Face| {
+                let origin = points.positions[face.corners[0]];
+                other.corners.iter().all(|&x| {
+                    let offset = points.positions[x] - origin;
+                    face.across
+                        .iter()
+                        .map(|e| e.dot(&offset).powi(2))
+                        .sum::<f32>()
+                        <= WELD * WELD
+                })
+            };
+            if !on(&faces[a], &faces[b]) || !on(&faces[b], &faces[a]) {
+                continue;
+            }
+            let Some(overlap) = clip(&positions(&faces[a]), &positions(&faces[b])) else {
+                continue;
+            };
+            let mut piece: Vec<usize> = overlap.into_iter().map(|x| points.weld(x)).collect();
+            piece.dedup();
+            while piece.len() > 1 && piece.first() == piece.last() {
+                piece.pop();
+            }
+            if piece.len() < 3 || width(&piece, &points.positions) <= EPSILON {
+                continue;
+            }
+            faces[a].pieces.push(pieces.len());
+            faces[b].pieces.push(pieces.len());
+            pieces.push(piece);
+        }
+    }
+    pieces
+}
+
+// The part of polygon a inside polygon b, which should be in the same plane
+fn clip(a: &[na::Vector4], b: &[na::Vector4]) -> Option<Vec<na::Vector4>> {
+    let b_centre = b.iter().sum::<na::Vector4>() / b.len() as f32;
+    let mut polygon = a.to_vec();
+    for i in 0..b.len() {
+        let (start, end) = (b[i], b[(i + 1) % b.len()]);
+        let along = end - start;
+        // Along the plane, at right angles to the edge, and pointing in
+        let inward = ((b_centre - start)
+            - along * along.dot(&(b_centre - start)) / along.norm_squared())
+        .try_normalize(0.0)?;
+        let distances: Vec<f32> = polygon.iter().map(|x| (x - start).dot(&inward)).collect();
+        let mut clipped = Vec::new();
+        for j in 0..polygon.len() {
+            let k = (j + 1) % polygon.len();
+            let (d, next) = (distances[j], distances[k]);
+            if d >= -EPSILON {
+                clipped.push(polygon[j]);
+            }
+            if (d > EPSILON && next < -EPSILON) || (d < -EPSILON && next > EPSILON) {
+                clipped.push(polygon[j].lerp(&polygon[k], d / (d - next)));
+            }
+        }
+        if clipped.len() < 3 {
+            return None;
+        }
+        polygon = clipped;
+    }
+    Some(polygon)
+}
+
+// Fans each piece into triangles. Any corners lying along a piece's edges are
+// added to it first, so that pieces meeting along an edge split it the same
+// way. Where every corner has others in line with it, the piece is fanned from
+// its middle instead, so that none of the triangles are flat.
+fn triangulate(pieces: &[Vec<usize>], points: &mut Points) -> Vec<Vec<[usize; 3]>> {
+    let mut used: Vec<usize> = pieces.concat();
+    used.sort_unstable();
+    used.dedup();
+    let (min, max) = used.iter().fold(
+        (
+            na::Vector4::repeat(f32::INFINITY),
+            na::Vector4::repeat(-f32::INFINITY),
+        ),
+        |(min, max), &x| (min.inf(&points.positions[x]), max.sup(&points.positions[x])),
+    );
+    // Boxes about the size of a piece
+    let size = ((max - min).amax() / (pieces.len() as f32).cbrt()).max(EPSILON);
+    let key = |x: na::Vector4| [0, 1, 2, 3].map(|i| ((x[i] - min[i]) / size).floor() as i64);
+    let mut grid: HashMap<[i64; 4], Vec<usize>> = HashMap::new();
+    for &point in used.iter() {
+        grid.entry(key(points.positions[point]))
+            .or_default()
+            .push(point);
+    }
+
+    let mut triangles: Vec<Vec<[usize; 3]>> = Vec::new();
+    for piece in pieces {
+        // Points on more than one edge, close to a sharp corner, only go on the
+        // one they're nearest
+        let mut on: Vec<(usize, usize, f32, f32)> = Vec::new();
+        for i in 0..piece.len() {
+            let (start, end) = (
+                points.positions[piece[i]],
+                points.positions[piece[(i + 1) % piece.len()]],
+            );
+            let length = (end - start).norm();
+            let (low, high) = (
+                key(start.inf(&end) - na::Vector4::repeat(EPSILON)),
+                key(start.sup(&end) + na::Vector4::repeat(EPSILON)),
+            );
+            for x in low[0]..=high[0] {
+                for y in low[1]..=high[1] {
+                    for z in low[2]..=high[2] {
+                        for w in low[3]..=high[3] {
+                            for &point in grid.get(&[x, y, z, w]).into_iter().flatten() {
+                                let offset = points.positions[point] - start;
+                                let t = offset.dot(&(end - start)) / length;
+                                let distance = (offset - (end - start) * (t / length)).norm();
+                                if t > EPSILON
+                                    && t < length - EPSILON
+                                    && distance <= EPSILON
+                                    && !piece.contains(&point)
+                                {
+                                    on.push((point, i, t, distance));
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        on.sort_by(|x, y| x.0.cmp(&y.0).then(x.3.total_cmp(&y.3)));
+        on.dedup_by_key(|x| x.0);
+        on.sort_by(|x, y| x.1.cmp(&y.1).then(x.2.total_cmp(&y.2)));
+        let mut corners = Vec::new();
+        for (i, &point) in piece.iter().enumerate() {
+            corners.push(point);
+            corners.extend(on.iter().filter(|x| x.1 == i).map(|x| x.0));
+        }
+        // Fan from a corner if there's one which doesn't have others in line
+        // with it along its edges, as those would make flat triangles
+        let n = corners.len();
+        let in_line = |a: usize, b: usize, c: usize| {
+            let [a, b, c] = [a, b, c].map(|i| points.positions[corners[i % n]]);
+            let longest = (b - a).norm().max((c - b).norm()).max((a - c).norm());
+            2.0 * triangle_area(a, b, c) <= EPSILON * longest
+        };
+        match (0..n).find(|&i| !in_line(i, i + 1, i + 2) && !in_line(i + n, i + n - 1, i + n - 2)) {
+            Some(i) => triangles.push(
+                (1..n - 1)
+                    .map(|j| [corners[i], corners[(i + j) % n], corners[(i + j + 1) % n]])
+                    .collect(),
+            ),
+            None => {
+                let middle = points.weld(centre(&corners, &points.positions));
+                triangles.push(
+                    (0..n)
+                        .map(|i| [middle, corners[i], corners[(i + 1) % n]])
+                        .collect(),
+                );
+            }
+        }
+    }
+    triangles
+}
+
+// Where the pieces of a cell end up, once it's been split by a hyperplane
+#[derive(Default)]
+struct Split {
+    coplanar_front: Vec<Cell>,
+    coplanar_back: Vec<Cell>,
+    front: Vec<Cell>,
+    back: Vec<Cell>,
+}
+
+impl Split {
+    fn add(&mut self, plane: &Plane, cell: Cell) {
+        let distances: Vec<f32> = cell
+            .corners
+            .iter()
+            .map(|x| plane.distance(x.vertex.position))
+            .collect();
+        let any_front = distances.iter().any(|&x| x > EPSILON);
+        let any_back = distances.iter().any(|&x| x < -EPSILON);
+        match (any_front, any_back) {
+            (false, false) => {
+                if plane.normal.dot(&cell.plane.normal) > 0.0 {
+                    self.coplanar_front.push(cell);
+                } else {
+                    self.coplanar_back.push(cell);
+                }
+            }
+            (true, false) => self.front.push(cell),
+            (false, true) => self.back.push(cell),
+            (true, true) => {
+                let (front, back) = cell.cut(plane, &distances);
+                self.front.push(front);
+                self.back.push(back);
+            }
+        }
+    }
+}
+
+struct Node {
+    plane: Option<Plane>,
+    front: Option<usize>,
+    back: Option<usize>,
+    // The cells lying in the plane
+    cells: Vec<Cell>,
+}
+
+impl Node {
+    fn new() -> Self {
+        Self {
+            plane: None,
+            front: None,
+            back: None,
+            cells: Vec::new(),
+        }
+    }
+}
+
+// The nodes are kept in a list rather than boxed in each other, and walked
+// without recursion, as trees built from convex shapes are as deep as they
+// have cells
+struct Bsp {
+    nodes: Vec<Node>,
+    // Around the cells the tree was made from. Anything outside is outside the
+    // solid, or inside it once it's been inverted.
+    min: na::Vector4,
+    max: na::Vector4,
+    inverted: bool,
+}
+
+impl Bsp {
+    fn new(cells: Vec<Cell>) -> Self {
+        let positions = || {
+            cells
+                .iter()
+                .flat_map(|x| x.corners.iter().map(|x| x.vertex.position))
+        };
+        let mut bsp = Self {
+            nodes: vec![Node::new()],
+            min: positions().fold(na::Vector4::repeat(f32::INFINITY), |a, b| a.inf(&b)),
+            max: positions().fold(na::Vector4::repeat(-f32::INFINITY), |a, b| a.sup(&b)),
+            inverted: false,
+        };
+        bsp.build(0, cells);
+        bsp
+    }
+
+    fn build(&mut self, node: usize, cells: Vec<Cell>) {
+        let mut stack = vec![(node, cells)];
+        while let Some((node, cells)) = stack.pop() {
+            if cells.is_empty() {
+                continue;
+            }
+            let plane = *self.nodes[node].plane.get_or_insert(cells[0].plane);
+            let mut split = Split::default();
+            for cell in cells {
+                split.add(&plane, cell);
+            }
+            self.nodes[node].cells.extend(split.coplanar_front);
+            self.nodes[node].cells.extend(split.coplanar_back);
+            for (cells, front) in [(split.front, true), (split.back, false)] {
+                if cells.is_empty() {
+                    continue;
+                }
+                let child = if front {
+                    self.nodes[node].front
+                } else {
+                    self.nodes[node].back
+                };
+                let child = child.unwrap_or_else(|| {
+                    self.nodes.push(Node::new());
+                    let child = self.nodes.len() - 1;
+                    if front {
+                        self.nodes[node].front = Some(child);
+                    } else {
+                        self.nodes[node].back = Some(child);
+                    }
+                    child
+                });
+                stack.push((child, cells));
+            }
+        }
+    }
+
+    // Swap inside and outside
+    fn invert(&mut self) {
+        self.inverted = !self.inverted;
+        for node in self.nodes.iter_mut() {
+            for cell in node.cells.iter_mut() {
+                cell.flip();
+            }
+            if let Some(plane) = node.plane.as_mut() {
+                plane.flip();
+            }
+            std::mem::swap(&mut node.front, &mut node.back);
+        }
+    }
+
+    fn overlaps(&self, cell: &Cell) -> bool {
+        let position = |i: usize| cell.corners[i].vertex.position;
+        let (min, max) = (1..cell.corners.len()).fold((position(0), position(0)), |(a, b), i| {
+            (a.inf(&position(i)), b.sup(&position(i)))
+        });
+        (0..4).all(|i| min[i] <= self.max[i] + EPSILON && max[i] >= self.min[i] - EPSILON)
+    }
+
+    // The parts of the cells outside the tree's solid. A cell which comes out of
+    // the tree in pieces, all kept, is put back together.
+    fn clip_cells(&self, cells: Vec<Cell>) -> Vec<Cell> {
+        let mut clipped = Vec::new();
+        let mut pending = Vec::new();
+        for (i, cell) in cells.iter().enumerate() {
+            if self.overlaps(cell) {
+                pending.push(Cell {
+                    source: i,
+                    ..cell.clone()
+                });
+            } else if !self.inverted {
+                clipped.push(cell.clone());
+            }
+        }
+
+        let mut pieces = Vec::new();
+        let mut dropped = vec![false; cells.len()];
+        let mut stack = vec![(0, pending)];
+        while let Some((node, cells)) = stack.pop() {
+            let node = &self.nodes[node];
+            let plane = match node.plane {
+                Some(plane) => plane,
+                None => {
+                    pieces.extend(cells);
+                    continue;
+                }
+            };
+            let mut split = Split::default();
+            for cell in cells {
+                split.add(&plane, cell);
+            }
+            let mut front = split.front;
+            front.extend(split.coplanar_front);
+            let mut back = split.back;
+            back.extend(split.coplanar_back);
+            match node.front {
+                Some(child) => stack.push((child, front)),
+                None => pieces.extend(front),
+            }
+            // Anything behind a leaf is inside, and dropped
+            match node.back {
+                Some(child) => stack.push((child, back)),
+                None => {
+                    for cell in back {
+                        dropped[cell.source] = true;
+                    }
+                }
+            }
+        }
+
+        let mut whole = vec![false; cells.len()];
+        for piece in pieces {
+            if !dropped[piece.source] {
+                whole[piece.source] = true;
+            } else {
+                clipped.push(piece);
+            }
+        }
+        for (cell, whole) in cells.into_iter().zip(whole) {
+            if whole {
+                clipped.push(cell);
+            }
+        }
+        clipped
+    }
+
+    // Remove the parts of this tree's cells inside the other tree's solid
+    fn clip_to(&mut self, other: &Bsp) {
+        for node in self.nodes.iter_mut() {
+            node.cells = other.clip_cells(std::mem::take(&mut node.cells));
+        }
+    }
+
+    fn all_cells(&self) -> Vec<Cell> {
+        self.nodes
+            .iter()
+            .flat_map(|node| node.cells.iter().cloned())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mesh;
+    use crate::mesh_builder::tests::{content, welded};
+    use crate::na;
+
+    // The result has to be closed and wound consistently, with nothing to fix
+    fn check(mesh: &mesh::Mesh4) -> mesh::Mesh4 {
+        let mut builder = welded(mesh);
+        builder.reorient = false;
+        builder.build().unwrap()
+    }
+
+    fn placed(mesh: mesh::Mesh4, position: na::Vector4, scale: f32) -> mesh::Mesh4 {
+        mesh.transformed(&na::Affine4::from_pos(
+            position,
+            na::Matrix4::identity(),
+            na::Vector4::repeat(scale),
+        ))
+    }
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-4, "{} isn't {}", a, b);
+    }
+
+    #[test]
+    fn tesseracts() {
+        let cube = mesh::Mesh4::cube();
+        for (offset, overlap) in [
+            (na::vec4(0.5, 0.0, 0.0, 0.0), 0.5),
+            (na::vec4(0.5, 0.25, 0.125, 0.0), 0.328125),
+        ] {
+            let other = placed(mesh::Mesh4::cube(), offset, 1.0);
+            assert_close(content(&check(&cube.union(&other))), 2.0 - overlap);
+            assert_close(content(&check(&cube.difference(&other))), 1.0 - overlap);
+            assert_close(content(&check(&cube.intersection(&other))), overlap);
+        }
+    }
+
+    #[test]
+    fn tesseract_and_glome() {
+        // The glome pokes out through the middle of each of the tesseract's
+        // cells, so every one of them is cut
+        let cube = mesh::Mesh4::cube();
+        let glome = placed(mesh::Mesh4::glome(4), na::Vector4::zeros(), 1.2);
+        let union = content(&check(&cube.union(&glome)));
+        let difference = content(&check(&cube.difference(&glome)));
+        let intersection = content(&check(&cube.intersection(&glome)));
+        assert_close(difference + intersection, 1.0);
+        assert_close(union - difference, content(&check(&glome)));
+    }
+}
@@ -0,0 +1,37 @@
+use crate::actor;
+use crate::collision;
+use crate::constraints;
+use crate::draw_state;
+use crate::mesh_renderer;
+
+// Remove an entity from the world, along with everything else which refers to
+// it - the solver would panic on the next step if a joint or arbiter was left
+// pointing at a body which no longer exists.
+pub fn despawn(
+    constraints: &mut constraints::Constraints,
+    world: &mut hecs::World,
+    entity: hecs::Entity,
+) -> Result<(), hecs::NoSuchEntity> {
+    if !world.contains(entity) {
+        return Err(hecs::NoSuchEntity);
+    }
+
+    actor::body_despawned(world, entity);
+
+    for other in constraints.remove_body(entity) {
+        world
+            .query_one_mut::<&mut draw_state::DrawState>(other)
+            .map(|x| x.contacts -= 1)
+            .ok();
+    }
+
+    for (_, sensor) in world.query_mut::<&mut collision::Sensor>() {
+        sensor.overlapping.retain(|&x| x != entity);
+    }
+
+    if let Ok(mesh_buffers) = world.remove_one::<mesh_renderer::MeshBuffers>(entity) {
+        mesh_buffers.destroy();
+    }
+
+    world.despawn(entity)
+}
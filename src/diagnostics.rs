@@ -0,0 +1,72 @@
+use crate::constraints;
+use crate::ga;
+use crate::ga::Wedge;
+use crate::gravity;
+use crate::na;
+use crate::physics;
+
+// Totals over every dynamic body in the world, for checking whether the solver
+// is adding or losing energy. Static and kinematic bodies have infinite mass, so
+// they're left out.
+#[derive(Debug, Clone, Copy)]
+pub struct Diagnostics {
+    pub linear_momentum: na::Vector4,
+    // About the reference point passed to measure
+    pub angular_momentum: ga::Bivector4,
+    pub translational_energy: f32,
+    pub rotational_energy: f32,
+    pub potential_energy: f32,
+    // The deepest any pair of bodies overlap at a contact
+    pub max_penetration: f32,
+}
+
+impl Diagnostics {
+    pub fn kinetic_energy(&self) -> f32 {
+        self.translational_energy + self.rotational_energy
+    }
+
+    pub fn total_energy(&self) -> f32 {
+        self.kinetic_energy() + self.potential_energy
+    }
+}
+
+pub fn measure(
+    gravity: &gravity::Gravity,
+    constraints: &constraints::Constraints,
+    world: &hecs::World,
+    reference: na::Vector4,
+) -> Diagnostics {
+    let gravity_field = gravity::Field::new(gravity, world);
+
+    let mut diagnostics = Diagnostics {
+        linear_momentum: na::Vector4::zeros(),
+        angular_momentum: ga::Bivector4::zero(),
+        translational_energy: 0.0,
+        rotational_energy: 0.0,
+        potential_energy: 0.0,
+        max_penetration: 0.0,
+    };
+    for (entity, body) in world.query::<&physics::RigidBody>().iter() {
+        if !body.is_dynamic() {
+            continue;
+        }
+        let momentum = body.mass * body.velocity;
+        let angular_momentum = body.angular_momentum();
+        diagnostics.linear_momentum += momentum;
+        diagnostics.angular_momentum +=
+            (body.position - reference).wedge(momentum) + angular_momentum;
+        diagnostics.translational_energy += 0.5 * momentum.dot(&body.velocity);
+        diagnostics.rotational_energy += 0.5
+            * angular_momentum
+                .as_vector()
+                .dot(&body.angular_velocity.as_vector());
+        diagnostics.potential_energy +=
+            body.mass * body.gravity * gravity_field.potential(entity, body.position);
+    }
+    diagnostics.max_penetration = constraints
+        .arbiters()
+        .flat_map(|arbiter| arbiter.contacts.iter())
+        .map(|contact| contact.depth())
+        .fold(0.0, f32::max);
+    diagnostics
+}
@@ -0,0 +1,181 @@
+use crate::collision;
+use crate::gravity;
+use crate::mesh;
+use crate::na;
+use crate::physics;
+
+use crate::ga::Wedge;
+
+// Quadratic air resistance, opposing motion relative to the air. The
+// coefficients bundle up the air's density, the body's shape and its
+// cross-section.
+#[derive(Debug, Clone, Copy)]
+pub struct Drag {
+    pub linear: f32,
+    pub angular: f32,
+}
+
+// Moves the air for anything overlapping the Sensor on the same entity, so
+// bodies with Drag are blown along with it
+#[derive(Debug, Clone, Copy)]
+pub struct Wind {
+    pub velocity: na::Vector4,
+}
+
+// Fluid filling everything below a hyperplane, for anything overlapping the
+// Sensor on the same entity. Bodies with a Mesh4 are pushed up by the weight of
+// the fluid their submerged hypervolume displaces, and slowed in proportion to
+// it.
+#[derive(Debug, Clone, Copy)]
+pub struct Fluid {
+    // The surface is where normal.dot(point) == level, with the normal pointing
+    // up out of the fluid
+    pub normal: na::Vector4,
+    pub level: f32,
+    pub density: f32,
+    pub drag: f32,
+}
+
+// Split a triangular prism into tetrahedra, given the triangles at each end with
+// matching vertices in the same order
+fn split_prism(p: [na::Vector4; 3], q: [na::Vector4; 3]) -> [[na::Vector4; 4]; 3] {
+    [
+        [p[0], p[1], p[2], q[0]],
+        [p[1], p[2], q[0], q[1]],
+        [p[2], q[0], q[1], q[2]],
+    ]
+}
+
+// The parts of a tetrahedron below the surface of a fluid, as tetrahedra
+fn clip_below(v: [na::Vector4; 4], fluid: &Fluid) -> Vec<[na::Vector4; 4]> {
+    let height = v.map(|p| fluid.normal.dot(&p) - fluid.level);
+    let (below, above): (Vec<usize>, Vec<usize>) = (0..4).partition(|&i| height[i] < 0.0);
+    // Where the edge from a vertex below to one above crosses the surface
+    let cut = |i: usize, j: usize| v[i] + (v[j] - v[i]) * (height[i] / (height[i] - height[j]));
+    match (below.as_slice(), above.as_slice()) {
+        (_, []) => vec![v],
+        ([], _) => vec![],
+        (&[a], &[b, c, d]) => vec![[v[a], cut(a, b), cut(a, c), cut(a, d)]],
+        (&[a, b], &[c, d]) => {
+            split_prism([v[a], cut(a, c), cut(a, d)], [v[b], cut(b, c), cut(b, d)]).to_vec()
+        }
+        (&[a, b, c], &[d]) => {
+            split_prism([v[a], v[b], v[c]], [cut(a, d), cut(b, d), cut(c, d)]).to_vec()
+        }
+        _ => unreachable!(),
+    }
+}
+
+// The hypervolume of a closed mesh which lies below the surface of a fluid, and
+// its centroid. Each boundary tetrahedron below the surface forms a pyramid with
+// a point on the surface, and the signed hypervolumes of the pyramids add up to
+// the submerged hypervolume - the part of the boundary cut off by the surface
+// lies in the same hyperplane as the apex, so contributes nothing.
+pub fn submerged_volume(
+    mesh: &mesh::Mesh4,
+    transform: &na::Affine4,
+    fluid: &Fluid,
+) -> (f32, na::Vector4) {
+    let origin = *transform * na::Vector4::zeros();
+    let apex = origin - fluid.normal * (fluid.normal.dot(&origin) - fluid.level);
+
+    let mut volume = 0.0;
+    let mut moment = na::Vector4::zeros();
+    for (cell, normal) in mesh.cells() {
+        let cell = cell.map(|p| *transform * p);
+        let normal = transform.linear * normal;
+        for [a, b, c, d] in clip_below(cell, fluid) {
+            let base = (b - a).wedge((c - a).wedge(d - a)).norm() / 6.0;
+            let pyramid = base * normal.dot(&(a - apex)) / 4.0;
+            volume += pyramid;
+            moment += pyramid * (apex + a + b + c + d) / 5.0;
+        }
+    }
+    if volume > 0.0 {
+        (volume, moment / volume)
+    } else {
+        (0.0, origin)
+    }
+}
+
+// Slow a velocity relative to the surrounding fluid by quadratic drag, without
+// overshooting and reversing it
+fn drag_factor(coefficient: f32, speed: f32, dt: f32, inverse_mass: f32) -> f32 {
+    (coefficient * speed * dt * inverse_mass).min(1.0)
+}
+
+// The bodies which apply_forces might push - those with drag, and those in a
+// fluid
+pub fn affected(world: &hecs::World) -> std::collections::HashSet<hecs::Entity> {
+    let mut affected: std::collections::HashSet<hecs::Entity> = world
+        .query::<&Drag>()
+        .iter()
+        .map(|(entity, _)| entity)
+        .collect();
+    for (_, (sensor, _)) in world.query::<(&collision::Sensor, &Fluid)>().iter() {
+        affected.extend(sensor.overlapping.iter().copied());
+    }
+    affected
+}
+
+pub fn apply_forces(dt: f32, gravity_field: &gravity::Field, world: &mut hecs::World) {
+    let mut winds = std::collections::HashMap::new();
+    for (_, (sensor, wind)) in world.query::<(&collision::Sensor, &Wind)>().iter() {
+        for entity in sensor.overlapping.iter() {
+            winds.entry(*entity).or_insert(wind.velocity);
+        }
+    }
+    let mut fluids = std::collections::HashMap::new();
+    for (_, (sensor, fluid)) in world.query::<(&collision::Sensor, &Fluid)>().iter() {
+        for entity in sensor.overlapping.iter() {
+            fluids.entry(*entity).or_insert(*fluid);
+        }
+    }
+
+    for (entity, (body, drag, mesh)) in
+        world.query_mut::<(&mut physics::RigidBody, Option<&Drag>, Option<&mesh::Mesh4>)>()
+    {
+        if !body.is_dynamic() {
+            continue;
+        }
+
+        if let Some(drag) = drag {
+            let wind = winds
+                .get(&entity)
+                .copied()
+                .unwrap_or_else(na::Vector4::zeros);
+            let relative_velocity = body.velocity - wind;
+            body.velocity -= relative_velocity
+                * drag_factor(drag.linear, relative_velocity.norm(), dt, body.inverse_mass);
+            body.angular_velocity *= 1.0
+                - drag_factor(
+                    drag.angular,
+                    body.angular_velocity.norm(),
+                    dt,
+                    body.inverse_inertia_in(body.angular_velocity),
+                );
+        }
+
+        if let (Some(fluid), Some(mesh)) = (fluids.get(&entity), mesh) {
+            let (volume, centroid) = submerged_volume(mesh, &body.get_transform(), fluid);
+            if volume > 0.0 {
+                let weight = gravity_field.acceleration(entity, body.position) * body.gravity;
+                let buoyancy = -fluid.density * volume * weight;
+                body.velocity += dt * body.inverse_mass * buoyancy;
+                body.angular_velocity +=
+                    dt * body.inverse_inertia_tensor * (centroid - body.position).wedge(buoyancy);
+
+                let coefficient = fluid.drag * volume;
+                body.velocity *=
+                    1.0 - drag_factor(coefficient, body.velocity.norm(), dt, body.inverse_mass);
+                body.angular_velocity *= 1.0
+                    - drag_factor(
+                        coefficient,
+                        body.angular_velocity.norm(),
+                        dt,
+                        body.inverse_inertia_in(body.angular_velocity),
+                    );
+            }
+        }
+    }
+}
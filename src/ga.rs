@@ -48,6 +48,66 @@ impl Bivector4 {
         na::Vector6::from_column_slice(&self.c)
     }
 
+    // The bivector as a skew-symmetric matrix, and back again
+    #[rustfmt::skip]
+    fn as_matrix(&self) -> na::Matrix4 {
+        na::Matrix4::new(
+                0.0,  self[0],  self[1],  self[2],
+           -self[0],      0.0,  self[3],  self[4],
+           -self[1], -self[3],      0.0,  self[5],
+           -self[2], -self[4], -self[5],      0.0,
+        )
+    }
+
+    fn from_matrix(m: &na::Matrix4) -> Self {
+        Self {
+            c: [
+                m[(0, 1)],
+                m[(0, 2)],
+                m[(0, 3)],
+                m[(1, 2)],
+                m[(1, 3)],
+                m[(2, 3)],
+            ],
+        }
+    }
+
+    // The bivector after applying the linear transformation m to the plane(s) it
+    // represents, i.e. m * B * m^T treating B as a skew-symmetric matrix
+    pub fn transformed(&self, m: &na::Matrix4) -> Self {
+        Self::from_matrix(&(m * self.as_matrix() * m.transpose()))
+    }
+
+    // The matrix which does the same as transformed(m) to the coefficient vector
+    // of a bivector. If m is a rotation, this is how an inertia tensor is moved
+    // between local and world space.
+    pub fn transformation_matrix(m: &na::Matrix4) -> na::Matrix6 {
+        na::Matrix6::from_fn(|i, j| {
+            let mut basis = Self::zero();
+            basis.c[j] = 1.0;
+            basis.transformed(m)[i]
+        })
+    }
+
+    // The commutator product AB - BA, which is what's left of the geometric
+    // product of two bivectors after the scalar and quadvector parts. For an
+    // angular velocity w, w.commutator(B) is how quickly B turns as it's carried
+    // around by the rotation (with the opposite sign).
+    pub fn commutator(&self, other: &Bivector4) -> Self {
+        let (a, b) = (self.as_matrix(), other.as_matrix());
+        Self::from_matrix(&(a * b - b * a))
+    }
+
+    // The matrix which, multiplied by the coefficient vector of B, gives the
+    // commutator of this bivector and B
+    pub fn commutator_matrix(&self) -> na::Matrix6 {
+        na::Matrix6::from_fn(|i, j| {
+            let mut basis = Self::zero();
+            basis.c[j] = 1.0;
+            self.commutator(&basis)[i]
+        })
+    }
+
     pub fn dot(&self, v: &na::Vector4) -> na::Vector4 {
         na::Vector4::new(
             -self[0] * v[1] - self[1] * v[2] - self[2] * v[3],
@@ -114,6 +174,15 @@ impl Mul<Bivector4> for f32 {
     }
 }
 
+// Applying a 6x6 matrix such as an inertia tensor to the bivector's coefficients
+impl Mul<Bivector4> for na::Matrix6 {
+    type Output = Bivector4;
+
+    fn mul(self, rhs: Bivector4) -> Bivector4 {
+        Bivector4::from_vector(self * rhs.as_vector())
+    }
+}
+
 impl Mul<f32> for Bivector4 {
     type Output = Self;
 
@@ -178,6 +247,36 @@ impl Rotor4 {
         Rotor4 { c }
     }
 
+    // The inverse rotation
+    pub fn reverse(&self) -> Rotor4 {
+        let [c0, c1, c2, c3, c4, c5, c6, c7] = self.c;
+        Rotor4 {
+            c: [c0, -c1, -c2, -c3, -c4, -c5, -c6, c7],
+        }
+    }
+
+    // The bivector which from_bivector would turn into this rotor. This ignores
+    // the quadvector part, so it's only exact for simple rotations - for the small
+    // per-step rotations it's used for, it's close enough.
+    pub fn log(&self) -> Bivector4 {
+        let bv = Bivector4 {
+            c: self.c[1..7].try_into().unwrap(),
+        };
+        let m = bv.norm();
+        if m == 0.0 {
+            Bivector4::zero()
+        } else {
+            bv * (m.atan2(self.c[0]) / m)
+        }
+    }
+
+    // The rotation a fraction t of the way from this one to other, turning at a
+    // constant rate. This goes through log, so it's only exact for simple
+    // rotations, but between consecutive physics steps they're close enough.
+    pub fn slerp(&self, other: &Rotor4, t: f32) -> Rotor4 {
+        *self * Rotor4::from_bivector((self.reverse() * *other).log() * t)
+    }
+
     pub fn to_matrix(self) -> na::Matrix4 {
         let [c0, c1, c2, c3, c4, c5, c6, c7] = self.c;
 
@@ -237,6 +336,16 @@ impl Trivector4 {
     pub fn norm_squared(&self) -> f32 {
         self.c.iter().map(|&x| x * x).sum()
     }
+
+    pub fn norm(&self) -> f32 {
+        self.norm_squared().sqrt()
+    }
+
+    // A vector perpendicular to the 3-space of this trivector, with the same
+    // magnitude - the 4D equivalent of the cross product
+    pub fn normal(&self) -> na::Vector4 {
+        na::vec4(self.c[3], -self.c[2], self.c[1], -self.c[0])
+    }
 }
 
 impl Index<usize> for Trivector4 {
@@ -0,0 +1,124 @@
+use crate::collision;
+use crate::na;
+use crate::physics;
+
+// The gravity acting everywhere in the world, unless a zone or override says
+// otherwise
+#[derive(Debug, Clone, Copy)]
+pub struct Gravity {
+    pub vector: na::Vector4,
+}
+
+impl Default for Gravity {
+    fn default() -> Self {
+        Self {
+            vector: na::vec4(0.0, -10.0, 0.0, 0.0),
+        }
+    }
+}
+
+// Pulls every body towards the position of its own entity's body. In 4D, the
+// field of a point mass spreads out over the surface of a glome, which grows
+// with the cube of its radius, so the pull falls off with the inverse cube of
+// distance. Within the radius it's the field inside a solid glome instead,
+// which shrinks linearly to nothing at the centre.
+#[derive(Debug, Clone, Copy)]
+pub struct Attractor {
+    pub strength: f32,
+    pub radius: f32,
+}
+
+impl Attractor {
+    // Acceleration towards the attractor, given the offset to it
+    pub fn acceleration(&self, offset: na::Vector4) -> na::Vector4 {
+        let distance = offset.norm().max(self.radius);
+        offset * self.strength / distance.powi(4)
+    }
+
+    // Potential energy per unit mass at the given offset from the attractor,
+    // which is zero infinitely far away
+    pub fn potential(&self, offset: na::Vector4) -> f32 {
+        let distance = offset.norm();
+        if distance > self.radius {
+            -self.strength / (2.0 * distance * distance)
+        } else {
+            self.strength * (distance * distance / self.radius.powi(4) - 2.0 / self.radius.powi(2))
+                / 2.0
+        }
+    }
+}
+
+// Replaces the world gravity for anything overlapping the Sensor on the same
+// entity
+#[derive(Debug, Clone, Copy)]
+pub struct Zone {
+    pub vector: na::Vector4,
+}
+
+// Replaces the world or zone gravity for a single body. Attractors still apply.
+#[derive(Debug, Clone, Copy)]
+pub struct Override {
+    pub vector: na::Vector4,
+}
+
+// Everything needed to find the acceleration due to gravity of any body,
+// gathered from the world once per step
+pub struct Field {
+    vector: na::Vector4,
+    attractors: Vec<(hecs::Entity, na::Vector4, Attractor)>,
+    // Bodies whose uniform gravity isn't the world's, from zones and overrides
+    uniform: std::collections::HashMap<hecs::Entity, na::Vector4>,
+}
+
+impl Field {
+    pub fn new(gravity: &Gravity, world: &hecs::World) -> Self {
+        let attractors = world
+            .query::<(&physics::RigidBody, &Attractor)>()
+            .iter()
+            .map(|(entity, (body, attractor))| (entity, body.position, *attractor))
+            .collect();
+
+        let mut uniform = std::collections::HashMap::new();
+        for (_, (sensor, zone)) in world.query::<(&collision::Sensor, &Zone)>().iter() {
+            for entity in sensor.overlapping.iter() {
+                uniform.entry(*entity).or_insert(zone.vector);
+            }
+        }
+        for (entity, gravity_override) in world.query::<&Override>().iter() {
+            uniform.insert(entity, gravity_override.vector);
+        }
+
+        Self {
+            vector: gravity.vector,
+            attractors,
+            uniform,
+        }
+    }
+
+    pub fn acceleration(&self, entity: hecs::Entity, position: na::Vector4) -> na::Vector4 {
+        let mut acceleration = self.uniform.get(&entity).copied().unwrap_or(self.vector);
+        for (attractor_entity, attractor_position, attractor) in self.attractors.iter() {
+            if *attractor_entity != entity {
+                acceleration += attractor.acceleration(attractor_position - position);
+            }
+        }
+        acceleration
+    }
+
+    // Potential energy per unit mass. Uniform gravity is measured from the
+    // origin, so only changes in this are meaningful.
+    pub fn potential(&self, entity: hecs::Entity, position: na::Vector4) -> f32 {
+        let mut potential = -self
+            .uniform
+            .get(&entity)
+            .copied()
+            .unwrap_or(self.vector)
+            .dot(&position);
+        for (attractor_entity, attractor_position, attractor) in self.attractors.iter() {
+            if *attractor_entity != entity {
+                potential += attractor.potential(attractor_position - position);
+            }
+        }
+        potential
+    }
+}
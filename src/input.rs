@@ -20,12 +20,17 @@ pub struct InputState {
 
     pub grab: bool,
     pub center_grab: bool,
+    // While grabbing - turn the held object with the mouse instead of looking
+    // around
+    pub rotate_held: bool,
+    pub scroll: f32,
 }
 
 impl InputState {
     pub fn new_events(&mut self) {
         self.yaw = 0.0;
         self.pitch = 0.0;
+        self.scroll = 0.0;
     }
 
     pub fn keyboard_event(&mut self, key_code: VirtualKeyCode, pressed: bool) {
@@ -43,6 +48,7 @@ impl InputState {
             VirtualKeyCode::E => self.roll_right = pressed,
 
             VirtualKeyCode::LShift => self.hyperlook = pressed,
+            VirtualKeyCode::LControl => self.rotate_held = pressed,
 
             _ => {}
         }
@@ -58,4 +64,8 @@ impl InputState {
             self.grab = pressed;
         }
     }
+
+    pub fn mouse_scrolled(&mut self, delta: f32) {
+        self.scroll += delta;
+    }
 }
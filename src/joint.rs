@@ -2,8 +2,512 @@ use crate::ga;
 use crate::na;
 use crate::physics;
 
+use crate::ga::Wedge;
+
+// Lets a joint stored as a trait object be turned back into its concrete type
+pub trait AsAny {
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any;
+}
+
+impl<T: std::any::Any> AsAny for T {
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+}
+
+// Anything which constrains the relative motion of two bodies. Impulses are
+// accumulated between steps, and applied again in prepare to warm start the
+// solver.
+pub trait Joint: AsAny {
+    fn prepare(
+        &mut self,
+        dt: f32,
+        settings: &physics::SolverSettings,
+        a_body: &mut physics::RigidBody,
+        b_body: &mut physics::RigidBody,
+    );
+
+    fn apply(&mut self, a_body: &mut physics::RigidBody, b_body: &mut physics::RigidBody);
+
+    // Correct position error using the pseudo-velocities only
+    fn apply_position(&mut self, a_body: &mut physics::RigidBody, b_body: &mut physics::RigidBody);
+
+    // The magnitudes of the linear and angular impulses applied by the joint in
+    // the current step
+    fn impulse(&self) -> (f32, f32);
+
+    // Whether the joint has failed, and should be removed from the solver
+    fn is_broken(&self) -> bool {
+        false
+    }
+}
+
+// Sent when a breakable joint between two bodies fails
+#[derive(Debug, Clone, Copy)]
+pub struct JointBroken {
+    pub a: hecs::Entity,
+    pub b: hecs::Entity,
+}
+
+fn to_local(body: &physics::RigidBody, world_space: na::Vector4) -> na::Vector4 {
+    body.get_transform().inverse() * world_space
+}
+
+// The relative orientation of b with respect to a, used as the reference when
+// measuring angular error
+fn relative_orientation(a_body: &physics::RigidBody, b_body: &physics::RigidBody) -> ga::Rotor4 {
+    b_body.orientation * a_body.orientation.reverse()
+}
+
+// The world-space rotation from where b should be, given a's orientation and
+// the reference, to where it actually is
+fn angular_error(
+    reference: ga::Rotor4,
+    a_body: &physics::RigidBody,
+    b_body: &physics::RigidBody,
+) -> ga::Bivector4 {
+    (a_body.orientation.reverse() * reference.reverse() * b_body.orientation).log()
+}
+
+// Complete the given orthonormal vectors to an orthonormal basis of 4D space,
+// with the given vectors as the first columns
+fn complete_basis(vectors: &[na::Vector4]) -> na::Matrix4 {
+    let mut basis: Vec<na::Vector4> = vectors.to_vec();
+    for i in 0..4 {
+        if basis.len() == 4 {
+            break;
+        }
+        let mut v = na::Vector4::ith(i, 1.0);
+        for b in basis.iter() {
+            v -= b * b.dot(&v);
+        }
+        if v.norm() > 0.1 {
+            basis.push(v.normalize());
+        }
+    }
+    na::Matrix4::from_columns(&basis)
+}
+
+// The combined linear and angular impulses of a set of rows, along with any
+// other impulses which aren't represented by rows
+fn total_impulse<'a>(rows: impl IntoIterator<Item = &'a Row>, other: (f32, f32)) -> (f32, f32) {
+    let (mut linear, mut angular) = (other.0 * other.0, other.1 * other.1);
+    for row in rows {
+        if row.a_linear == na::Vector4::zeros() {
+            angular += row.impulse * row.impulse;
+        } else {
+            linear += row.impulse * row.impulse;
+        }
+    }
+    (linear.sqrt(), angular.sqrt())
+}
+
+// A single degree of freedom removed from the relative motion of two bodies.
+// The jacobian maps the velocities of the bodies to the rate of change of the
+// constraint, and impulses are applied along it. The constraint is measured as
+// "a minus b", the same way as BallJoint.
+#[derive(Debug, Clone)]
+struct Row {
+    a_linear: na::Vector4,
+    a_angular: na::Vector6,
+    b_linear: na::Vector4,
+    b_angular: na::Vector6,
+
+    effective_mass: f32,
+    bias: f32,
+    // Soft constraints (springs) feed back some of the accumulated impulse
+    softness: f32,
+    lower: f32,
+    upper: f32,
+    impulse: f32,
+
+    position_bias: f32,
+    pseudo_impulse: f32,
+}
+
+impl Default for Row {
+    fn default() -> Self {
+        Self {
+            a_linear: na::Vector4::zeros(),
+            a_angular: na::Vector6::zeros(),
+            b_linear: na::Vector4::zeros(),
+            b_angular: na::Vector6::zeros(),
+
+            effective_mass: 0.0,
+            bias: 0.0,
+            softness: 0.0,
+            lower: f32::NEG_INFINITY,
+            upper: f32::INFINITY,
+            impulse: 0.0,
+
+            position_bias: 0.0,
+            pseudo_impulse: 0.0,
+        }
+    }
+}
+
+impl Row {
+    // Constrain the motion of the anchor points along a direction
+    fn set_linear(&mut self, direction: na::Vector4, a_arm: na::Vector4, b_arm: na::Vector4) {
+        self.a_linear = direction;
+        self.a_angular = a_arm.wedge(direction).as_vector();
+        self.b_linear = direction;
+        self.b_angular = b_arm.wedge(direction).as_vector();
+    }
+
+    // Constrain the rotation in a plane, given as a unit bivector
+    fn set_angular(&mut self, plane: ga::Bivector4) {
+        self.a_linear = na::Vector4::zeros();
+        self.a_angular = plane.as_vector();
+        self.b_linear = na::Vector4::zeros();
+        self.b_angular = plane.as_vector();
+    }
+
+    fn velocity(&self, a_body: &physics::RigidBody, b_body: &physics::RigidBody) -> f32 {
+        self.a_linear.dot(&a_body.velocity)
+            + self.a_angular.dot(&a_body.angular_velocity.as_vector())
+            - self.b_linear.dot(&b_body.velocity)
+            - self.b_angular.dot(&b_body.angular_velocity.as_vector())
+    }
+
+    fn pseudo_velocity(&self, a_body: &physics::RigidBody, b_body: &physics::RigidBody) -> f32 {
+        self.a_linear.dot(&a_body.pseudo_velocity)
+            + self
+                .a_angular
+                .dot(&a_body.pseudo_angular_velocity.as_vector())
+            - self.b_linear.dot(&b_body.pseudo_velocity)
+            - self
+                .b_angular
+                .dot(&b_body.pseudo_angular_velocity.as_vector())
+    }
+
+    fn apply_impulse(
+        &self,
+        impulse: f32,
+        a_body: &mut physics::RigidBody,
+        b_body: &mut physics::RigidBody,
+    ) {
+        a_body.velocity += a_body.inverse_mass * impulse * self.a_linear;
+        a_body.angular_velocity +=
+            ga::Bivector4::from_vector(a_body.inverse_inertia_tensor * impulse * self.a_angular);
+        b_body.velocity -= b_body.inverse_mass * impulse * self.b_linear;
+        b_body.angular_velocity -=
+            ga::Bivector4::from_vector(b_body.inverse_inertia_tensor * impulse * self.b_angular);
+    }
+
+    fn apply_pseudo_impulse(
+        &self,
+        impulse: f32,
+        a_body: &mut physics::RigidBody,
+        b_body: &mut physics::RigidBody,
+    ) {
+        a_body.pseudo_velocity += a_body.inverse_mass * impulse * self.a_linear;
+        a_body.pseudo_angular_velocity +=
+            ga::Bivector4::from_vector(a_body.inverse_inertia_tensor * impulse * self.a_angular);
+        b_body.pseudo_velocity -= b_body.inverse_mass * impulse * self.b_linear;
+        b_body.pseudo_angular_velocity -=
+            ga::Bivector4::from_vector(b_body.inverse_inertia_tensor * impulse * self.b_angular);
+    }
+
+    fn inverse_effective_mass(
+        &self,
+        a_body: &physics::RigidBody,
+        b_body: &physics::RigidBody,
+    ) -> f32 {
+        a_body.inverse_mass * self.a_linear.norm_squared()
+            + self
+                .a_angular
+                .dot(&(a_body.inverse_inertia_tensor * self.a_angular))
+            + b_body.inverse_mass * self.b_linear.norm_squared()
+            + self
+                .b_angular
+                .dot(&(b_body.inverse_inertia_tensor * self.b_angular))
+    }
+
+    // Set up a rigid constraint, given its current position error
+    fn prepare(
+        &mut self,
+        dt: f32,
+        settings: &physics::SolverSettings,
+        error: f32,
+        a_body: &mut physics::RigidBody,
+        b_body: &mut physics::RigidBody,
+    ) {
+        let k = self.inverse_effective_mass(a_body, b_body);
+        self.effective_mass = if k > 0.0 { 1.0 / k } else { 0.0 };
+        self.softness = 0.0;
+
+        let error = error * settings.joint_bias_factor / dt;
+        match settings.position_correction {
+            physics::PositionCorrection::Baumgarte => {
+                self.bias = error;
+                self.position_bias = 0.0;
+            }
+            physics::PositionCorrection::SplitImpulse => {
+                self.bias = 0.0;
+                self.position_bias = error;
+            }
+        }
+        self.pseudo_impulse = 0.0;
+
+        self.warm_start(settings, a_body, b_body);
+    }
+
+    // Set up one side of a limit, given the current error relative to it. While
+    // the limit isn't reached, the bodies may move up to it in a single step,
+    // rather than only correcting a fraction of the error.
+    fn prepare_limit(
+        &mut self,
+        dt: f32,
+        settings: &physics::SolverSettings,
+        error: f32,
+        within: bool,
+        a_body: &mut physics::RigidBody,
+        b_body: &mut physics::RigidBody,
+    ) {
+        self.prepare(dt, settings, error, a_body, b_body);
+        if within {
+            self.bias = error / dt;
+            self.position_bias = 0.0;
+        }
+    }
+
+    // Set up a rigid constraint which drives the bodies at a relative speed,
+    // rather than holding them in place
+    fn prepare_velocity(
+        &mut self,
+        dt: f32,
+        settings: &physics::SolverSettings,
+        speed: f32,
+        a_body: &mut physics::RigidBody,
+        b_body: &mut physics::RigidBody,
+    ) {
+        self.prepare(dt, settings, 0.0, a_body, b_body);
+        self.bias = -speed;
+    }
+
+    fn copy_jacobian(&mut self, other: &Row) {
+        self.a_linear = other.a_linear;
+        self.a_angular = other.a_angular;
+        self.b_linear = other.b_linear;
+        self.b_angular = other.b_angular;
+    }
+
+    // Set up a damped spring, given its current extension. This uses the "soft
+    // constraint" formulation, so it stays stable however stiff it is.
+    fn prepare_spring(
+        &mut self,
+        dt: f32,
+        extension: f32,
+        stiffness: f32,
+        damping: f32,
+        a_body: &physics::RigidBody,
+        b_body: &physics::RigidBody,
+    ) {
+        let k = self.inverse_effective_mass(a_body, b_body);
+        let gamma = dt * (damping + dt * stiffness);
+        self.softness = if gamma > 0.0 { 1.0 / gamma } else { 0.0 };
+        self.effective_mass = if k + self.softness > 0.0 {
+            1.0 / (k + self.softness)
+        } else {
+            0.0
+        };
+        self.bias = extension * dt * stiffness * self.softness;
+        self.position_bias = 0.0;
+        self.pseudo_impulse = 0.0;
+    }
+
+    fn warm_start(
+        &mut self,
+        settings: &physics::SolverSettings,
+        a_body: &mut physics::RigidBody,
+        b_body: &mut physics::RigidBody,
+    ) {
+        if !settings.warm_starting {
+            self.impulse = 0.0;
+        }
+        self.impulse = self.impulse.clamp(self.lower, self.upper);
+        self.apply_impulse(self.impulse, a_body, b_body);
+    }
+
+    fn apply(&mut self, a_body: &mut physics::RigidBody, b_body: &mut physics::RigidBody) {
+        let velocity = self.velocity(a_body, b_body);
+        let d_impulse =
+            -self.effective_mass * (velocity + self.bias + self.softness * self.impulse);
+
+        let impulse_original = self.impulse;
+        self.impulse = (self.impulse + d_impulse).clamp(self.lower, self.upper);
+        self.apply_impulse(self.impulse - impulse_original, a_body, b_body);
+    }
+
+    fn apply_position(&mut self, a_body: &mut physics::RigidBody, b_body: &mut physics::RigidBody) {
+        let velocity = self.pseudo_velocity(a_body, b_body);
+        let d_impulse = -self.effective_mass * (velocity + self.position_bias);
+
+        let impulse_original = self.pseudo_impulse;
+        self.pseudo_impulse = (self.pseudo_impulse + d_impulse).clamp(self.lower, self.upper);
+        self.apply_pseudo_impulse(self.pseudo_impulse - impulse_original, a_body, b_body);
+    }
+}
+
+// Locks the relative rotation of two bodies in a set of planes
+#[derive(Debug, Clone)]
+struct AngularLock<const N: usize> {
+    reference: ga::Rotor4,
+    rows: [Row; N],
+}
+
+impl<const N: usize> AngularLock<N> {
+    fn new(a_body: &physics::RigidBody, b_body: &physics::RigidBody) -> Self {
+        Self {
+            reference: relative_orientation(a_body, b_body),
+            rows: std::array::from_fn(|_| Row::default()),
+        }
+    }
+
+    // planes are world-space unit bivectors, and must be orthogonal to each other
+    fn prepare(
+        &mut self,
+        dt: f32,
+        settings: &physics::SolverSettings,
+        planes: &[ga::Bivector4; N],
+        a_body: &mut physics::RigidBody,
+        b_body: &mut physics::RigidBody,
+    ) {
+        // The error grows as b rotates away from where it should be, i.e. it
+        // shrinks with the relative angular velocity of a with respect to b
+        let error = angular_error(self.reference, a_body, b_body).as_vector();
+        for (row, plane) in self.rows.iter_mut().zip(planes.iter()) {
+            row.set_angular(*plane);
+            row.prepare(dt, settings, -error.dot(&plane.as_vector()), a_body, b_body);
+        }
+    }
+
+    fn apply(&mut self, a_body: &mut physics::RigidBody, b_body: &mut physics::RigidBody) {
+        for row in self.rows.iter_mut() {
+            row.apply(a_body, b_body);
+        }
+    }
+
+    fn apply_position(&mut self, a_body: &mut physics::RigidBody, b_body: &mut physics::RigidBody) {
+        for row in self.rows.iter_mut() {
+            row.apply_position(a_body, b_body);
+        }
+    }
+}
+
+// Drives a joint's free degree of freedom
+#[derive(Debug, Clone, Copy)]
+pub enum Motor {
+    // Turn or slide at a constant speed
+    Velocity {
+        speed: f32,
+        max_impulse: f32,
+    },
+    // Turn or slide towards a target angle or offset, no faster than max_speed
+    Position {
+        target: f32,
+        max_speed: f32,
+        max_impulse: f32,
+    },
+}
+
+// Limits and a motor acting on a single free degree of freedom of a joint - the
+// angle in a hinge's plane, or the offset along a slider's axis. Impulses are
+// limited to max_impulse per step, so a motor can be overpowered.
+#[derive(Debug, Clone, Default)]
+pub struct Drive {
+    pub limits: Option<(f32, f32)>,
+    pub motor: Option<Motor>,
+
+    lower: Row,
+    upper: Row,
+    motor_row: Row,
+}
+
+impl Drive {
+    // The jacobian row maps the body velocities to the rate of change of position
+    fn prepare(
+        &mut self,
+        dt: f32,
+        settings: &physics::SolverSettings,
+        jacobian: &Row,
+        position: f32,
+        a_body: &mut physics::RigidBody,
+        b_body: &mut physics::RigidBody,
+    ) {
+        match self.limits {
+            Some((min, max)) => {
+                self.lower.lower = 0.0;
+                self.upper.upper = 0.0;
+                for (row, error, within) in [
+                    (&mut self.lower, position - min, position > min),
+                    (&mut self.upper, position - max, position < max),
+                ] {
+                    row.copy_jacobian(jacobian);
+                    row.prepare_limit(dt, settings, error, within, a_body, b_body);
+                }
+            }
+            None => {
+                self.lower.impulse = 0.0;
+                self.upper.impulse = 0.0;
+            }
+        }
+
+        match self.motor {
+            Some(motor) => {
+                let (speed, max_impulse) = match motor {
+                    Motor::Velocity { speed, max_impulse } => (speed, max_impulse),
+                    Motor::Position {
+                        target,
+                        max_speed,
+                        max_impulse,
+                    } => (
+                        ((target - position) / dt).clamp(-max_speed, max_speed),
+                        max_impulse,
+                    ),
+                };
+                self.motor_row.lower = -max_impulse;
+                self.motor_row.upper = max_impulse;
+                self.motor_row.copy_jacobian(jacobian);
+                self.motor_row
+                    .prepare_velocity(dt, settings, speed, a_body, b_body);
+            }
+            None => self.motor_row.impulse = 0.0,
+        }
+    }
+
+    fn rows(&self) -> [&Row; 3] {
+        [&self.lower, &self.upper, &self.motor_row]
+    }
+
+    // The motor goes first, so that the limits have the last word
+    fn apply(&mut self, a_body: &mut physics::RigidBody, b_body: &mut physics::RigidBody) {
+        if self.motor.is_some() {
+            self.motor_row.apply(a_body, b_body);
+        }
+        if self.limits.is_some() {
+            self.lower.apply(a_body, b_body);
+            self.upper.apply(a_body, b_body);
+        }
+    }
+
+    // Motors don't correct errors, so only the limits take part here
+    fn apply_position(&mut self, a_body: &mut physics::RigidBody, b_body: &mut physics::RigidBody) {
+        if self.limits.is_some() {
+            self.lower.apply_position(a_body, b_body);
+            self.upper.apply_position(a_body, b_body);
+        }
+    }
+}
+
+fn basis_bivectors() -> [ga::Bivector4; 6] {
+    std::array::from_fn(|i| ga::Bivector4::from_vector(na::Vector6::ith(i, 1.0)))
+}
+
+// Pins a point on each body together, leaving them free to rotate
 #[derive(Debug)]
-pub struct Joint {
+pub struct BallJoint {
     pub a_anchor: na::Vector4,
     pub a_jacobian: na::Matrix4x6,
 
@@ -13,9 +517,13 @@ pub struct Joint {
     pub effective_mass: na::Matrix4,
     pub bias: na::Vector4,
     pub impulse: na::Vector4,
+
+    pub position_bias: na::Vector4,
+    pub pseudo_impulse: na::Vector4,
 }
 
-impl Joint {
+impl BallJoint {
+    // Anchors are in the local space of each body
     pub fn new(a_anchor: na::Vector4, b_anchor: na::Vector4) -> Self {
         Self {
             a_anchor,
@@ -27,12 +535,27 @@ impl Joint {
             effective_mass: na::Matrix4::zeros(),
             bias: na::Vector4::zeros(),
             impulse: na::Vector4::zeros(),
+
+            position_bias: na::Vector4::zeros(),
+            pseudo_impulse: na::Vector4::zeros(),
         }
     }
 
-    pub fn prepare(
+    // Pin the bodies together at a world-space point
+    pub fn at(
+        a_body: &physics::RigidBody,
+        b_body: &physics::RigidBody,
+        anchor: na::Vector4,
+    ) -> Self {
+        Self::new(to_local(a_body, anchor), to_local(b_body, anchor))
+    }
+}
+
+impl Joint for BallJoint {
+    fn prepare(
         &mut self,
         dt: f32,
+        settings: &physics::SolverSettings,
         a_body: &mut physics::RigidBody,
         b_body: &mut physics::RigidBody,
     ) {
@@ -44,23 +567,38 @@ impl Joint {
 
         // This is the main reason to convert bivectors to/from coefficient vectors -
         // I don't knoww another way to incorporate the inverse inertia tensor into
-        // this calculation. We're just using a single float for now, but it should be
-        // a 6x6 matrix representing the moments in each combination of the 6
-        // basis bivectors.
+        // this calculation, which is a 6x6 matrix representing the moments in each
+        // combination of the 6 basis bivectors.
         // It would be nice to deal with this entirely via GA, but I don't know how,
         // and this seems to work.
+        // The sum is singular when neither body can move, and then the joint
+        // does nothing.
         self.effective_mass = (na::Matrix4::identity() * a_body.inverse_mass
             + self.a_jacobian * a_body.inverse_inertia_tensor * self.a_jacobian.transpose()
             + na::Matrix4::identity() * b_body.inverse_mass
             + self.b_jacobian * b_body.inverse_inertia_tensor * self.b_jacobian.transpose())
         .try_inverse()
-        .unwrap();
+        .unwrap_or_else(na::Matrix4::zeros);
 
         let a_world_space_anchor = a_world_orientation_anchor + a_body.position;
         let b_world_space_anchor = b_world_orientation_anchor + b_body.position;
-        self.bias = (a_world_space_anchor - b_world_space_anchor) * 0.5 / dt;
+        let error = (a_world_space_anchor - b_world_space_anchor) * settings.joint_bias_factor / dt;
+        match settings.position_correction {
+            physics::PositionCorrection::Baumgarte => {
+                self.bias = error;
+                self.position_bias = na::Vector4::zeros();
+            }
+            physics::PositionCorrection::SplitImpulse => {
+                self.bias = na::Vector4::zeros();
+                self.position_bias = error;
+            }
+        }
+        self.pseudo_impulse = na::Vector4::zeros();
 
         // Warm starting - impulse is likely to be similar to last frame's
+        if !settings.warm_starting {
+            self.impulse = na::Vector4::zeros();
+        }
         a_body.velocity += a_body.inverse_mass * self.impulse;
         a_body.angular_velocity += ga::Bivector4::from_vector(
             a_body.inverse_inertia_tensor * self.a_jacobian.transpose() * self.impulse,
@@ -71,7 +609,7 @@ impl Joint {
         );
     }
 
-    pub fn apply(&mut self, a_body: &mut physics::RigidBody, b_body: &mut physics::RigidBody) {
+    fn apply(&mut self, a_body: &mut physics::RigidBody, b_body: &mut physics::RigidBody) {
         let velocity = (a_body.velocity + self.a_jacobian * a_body.angular_velocity.as_vector())
             - (b_body.velocity + self.b_jacobian * b_body.angular_velocity.as_vector());
 
@@ -87,4 +625,749 @@ impl Joint {
             b_body.inverse_inertia_tensor * self.b_jacobian.transpose() * d_impulse,
         );
     }
+
+    // Same as apply, but operating on the pseudo-velocities to correct the
+    // position error only
+    fn apply_position(&mut self, a_body: &mut physics::RigidBody, b_body: &mut physics::RigidBody) {
+        let velocity = (a_body.pseudo_velocity
+            + self.a_jacobian * a_body.pseudo_angular_velocity.as_vector())
+            - (b_body.pseudo_velocity
+                + self.b_jacobian * b_body.pseudo_angular_velocity.as_vector());
+
+        let d_impulse = self.effective_mass * -(velocity + self.position_bias);
+        self.pseudo_impulse += d_impulse;
+
+        a_body.pseudo_velocity += a_body.inverse_mass * d_impulse;
+        a_body.pseudo_angular_velocity += ga::Bivector4::from_vector(
+            a_body.inverse_inertia_tensor * self.a_jacobian.transpose() * d_impulse,
+        );
+        b_body.pseudo_velocity -= b_body.inverse_mass * d_impulse;
+        b_body.pseudo_angular_velocity -= ga::Bivector4::from_vector(
+            b_body.inverse_inertia_tensor * self.b_jacobian.transpose() * d_impulse,
+        );
+    }
+
+    fn impulse(&self) -> (f32, f32) {
+        (self.impulse.norm(), 0.0)
+    }
+}
+
+// Glues two bodies together, removing all relative motion
+pub struct WeldJoint {
+    ball: BallJoint,
+    angular: AngularLock<6>,
+}
+
+impl WeldJoint {
+    pub fn new(
+        a_body: &physics::RigidBody,
+        b_body: &physics::RigidBody,
+        anchor: na::Vector4,
+    ) -> Self {
+        Self {
+            ball: BallJoint::at(a_body, b_body, anchor),
+            angular: AngularLock::new(a_body, b_body),
+        }
+    }
+}
+
+impl Joint for WeldJoint {
+    fn prepare(
+        &mut self,
+        dt: f32,
+        settings: &physics::SolverSettings,
+        a_body: &mut physics::RigidBody,
+        b_body: &mut physics::RigidBody,
+    ) {
+        self.angular
+            .prepare(dt, settings, &basis_bivectors(), a_body, b_body);
+        self.ball.prepare(dt, settings, a_body, b_body);
+    }
+
+    fn apply(&mut self, a_body: &mut physics::RigidBody, b_body: &mut physics::RigidBody) {
+        self.angular.apply(a_body, b_body);
+        self.ball.apply(a_body, b_body);
+    }
+
+    fn apply_position(&mut self, a_body: &mut physics::RigidBody, b_body: &mut physics::RigidBody) {
+        self.angular.apply_position(a_body, b_body);
+        self.ball.apply_position(a_body, b_body);
+    }
+
+    fn impulse(&self) -> (f32, f32) {
+        total_impulse(&self.angular.rows, self.ball.impulse())
+    }
+}
+
+// The 4D equivalent of a hinge. In 3D, a hinge leaves rotation free around an
+// axis, which is the same as leaving it free in the plane perpendicular to that
+// axis. In 4D there's no single perpendicular plane, so the free plane is given
+// directly - the bodies may rotate in that plane only, and are pinned together at
+// the anchor. The drive acts on the angle in the free plane.
+pub struct HingeJoint {
+    ball: BallJoint,
+    angular: AngularLock<5>,
+    // An orthonormal basis in a's local space - the first two columns span the
+    // free plane
+    a_basis: na::Matrix4,
+    // The free plane in a's local space, as a unit bivector
+    a_plane: ga::Bivector4,
+
+    pub drive: Drive,
+    drive_row: Row,
+}
+
+impl HingeJoint {
+    // The anchor is a world-space point, and the plane a world-space bivector
+    pub fn new(
+        a_body: &physics::RigidBody,
+        b_body: &physics::RigidBody,
+        anchor: na::Vector4,
+        plane: ga::Bivector4,
+    ) -> Self {
+        // Dotting a vector with a (simple) bivector gives a vector in its plane,
+        // rotated by 90 degrees within it - use that to find two orthogonal
+        // vectors spanning the plane
+        let local_plane = plane.transformed(&a_body.orientation.to_matrix().transpose());
+        let u = (0..4)
+            .map(|i| local_plane.dot(&na::Vector4::ith(i, 1.0)))
+            .max_by(|x, y| x.norm_squared().total_cmp(&y.norm_squared()))
+            .unwrap()
+            .normalize();
+        let v = local_plane.dot(&u).normalize();
+
+        Self {
+            ball: BallJoint::at(a_body, b_body, anchor),
+            angular: AngularLock::new(a_body, b_body),
+            a_basis: complete_basis(&[u, v]),
+            a_plane: local_plane / local_plane.norm(),
+
+            drive: Drive::default(),
+            drive_row: Row::default(),
+        }
+    }
+
+    // Limit the angle, in radians from the starting orientation. Positive angles
+    // rotate in the direction of the plane bivector.
+    pub fn with_limits(mut self, min: f32, max: f32) -> Self {
+        self.drive.limits = Some((min, max));
+        self
+    }
+
+    pub fn with_motor(mut self, motor: Motor) -> Self {
+        self.drive.motor = Some(motor);
+        self
+    }
+
+    // The current angle of b relative to a in the free plane. A rotor made from a
+    // bivector turns by twice its magnitude, so this is twice the angular error
+    // in the plane.
+    pub fn angle(&self, a_body: &physics::RigidBody, b_body: &physics::RigidBody) -> f32 {
+        let error = angular_error(self.angular.reference, a_body, b_body);
+        2.0 * error.as_vector().dot(&self.world_plane(a_body).as_vector())
+    }
+
+    fn world_basis(&self, a_body: &physics::RigidBody) -> na::Matrix4 {
+        a_body.orientation.to_matrix() * self.a_basis
+    }
+
+    fn world_plane(&self, a_body: &physics::RigidBody) -> ga::Bivector4 {
+        self.a_plane.transformed(&a_body.orientation.to_matrix())
+    }
+
+    // Every plane which isn't the free plane, in world space
+    fn locked_planes(&self, a_body: &physics::RigidBody) -> [ga::Bivector4; 5] {
+        let basis = self.world_basis(a_body);
+        let [u, v, x, y] = [0, 1, 2, 3].map(|i| basis.column(i).into_owned());
+        [u.wedge(x), u.wedge(y), v.wedge(x), v.wedge(y), x.wedge(y)]
+    }
+}
+
+impl Joint for HingeJoint {
+    fn prepare(
+        &mut self,
+        dt: f32,
+        settings: &physics::SolverSettings,
+        a_body: &mut physics::RigidBody,
+        b_body: &mut physics::RigidBody,
+    ) {
+        let planes = self.locked_planes(a_body);
+        self.angular.prepare(dt, settings, &planes, a_body, b_body);
+        self.ball.prepare(dt, settings, a_body, b_body);
+
+        // The angle grows as b rotates in the plane relative to a - at twice the
+        // angular velocity, as the orientations are integrated through rotors
+        self.drive_row.set_angular(-2.0 * self.world_plane(a_body));
+        let angle = self.angle(a_body, b_body);
+        self.drive
+            .prepare(dt, settings, &self.drive_row, angle, a_body, b_body);
+    }
+
+    fn apply(&mut self, a_body: &mut physics::RigidBody, b_body: &mut physics::RigidBody) {
+        self.drive.apply(a_body, b_body);
+        self.angular.apply(a_body, b_body);
+        self.ball.apply(a_body, b_body);
+    }
+
+    fn apply_position(&mut self, a_body: &mut physics::RigidBody, b_body: &mut physics::RigidBody) {
+        self.drive.apply_position(a_body, b_body);
+        self.angular.apply_position(a_body, b_body);
+        self.ball.apply_position(a_body, b_body);
+    }
+
+    fn impulse(&self) -> (f32, f32) {
+        total_impulse(
+            self.angular.rows.iter().chain(self.drive.rows()),
+            self.ball.impulse(),
+        )
+    }
+}
+
+// Lets the bodies slide along an axis fixed in a, with no relative rotation. The
+// drive acts on the offset along the axis.
+pub struct SliderJoint {
+    a_anchor: na::Vector4,
+    b_anchor: na::Vector4,
+    // An orthonormal basis in a's local space - the first column is the axis
+    a_basis: na::Matrix4,
+
+    linear: [Row; 3],
+    angular: AngularLock<6>,
+
+    pub drive: Drive,
+    drive_row: Row,
+}
+
+impl SliderJoint {
+    // The anchor is a world-space point, and the axis a world-space direction
+    pub fn new(
+        a_body: &physics::RigidBody,
+        b_body: &physics::RigidBody,
+        anchor: na::Vector4,
+        axis: na::Vector4,
+    ) -> Self {
+        let local_axis = a_body.orientation.to_matrix().transpose() * axis.normalize();
+        Self {
+            a_anchor: to_local(a_body, anchor),
+            b_anchor: to_local(b_body, anchor),
+            a_basis: complete_basis(&[local_axis]),
+
+            linear: std::array::from_fn(|_| Row::default()),
+            angular: AngularLock::new(a_body, b_body),
+
+            drive: Drive::default(),
+            drive_row: Row::default(),
+        }
+    }
+
+    // Limit the offset of b along the axis from its starting position
+    pub fn with_limits(mut self, min: f32, max: f32) -> Self {
+        self.drive.limits = Some((min, max));
+        self
+    }
+
+    pub fn with_motor(mut self, motor: Motor) -> Self {
+        self.drive.motor = Some(motor);
+        self
+    }
+
+    // The current offset of b along the axis
+    pub fn offset(&self, a_body: &physics::RigidBody, b_body: &physics::RigidBody) -> f32 {
+        let (_, _, axis, separation) = self.arms(a_body, b_body);
+        -separation.dot(&axis)
+    }
+
+    // The arms of each anchor, the axis in world space, and the separation of
+    // the anchors
+    fn arms(
+        &self,
+        a_body: &physics::RigidBody,
+        b_body: &physics::RigidBody,
+    ) -> (na::Vector4, na::Vector4, na::Vector4, na::Vector4) {
+        let a_orientation = a_body.orientation.to_matrix();
+        let a_arm = a_orientation * self.a_anchor;
+        let b_arm = b_body.orientation.to_matrix() * self.b_anchor;
+        let separation = (a_body.position + a_arm) - (b_body.position + b_arm);
+        let axis = a_orientation * self.a_basis.column(0);
+        (a_arm, b_arm, axis, separation)
+    }
+}
+
+impl Joint for SliderJoint {
+    fn prepare(
+        &mut self,
+        dt: f32,
+        settings: &physics::SolverSettings,
+        a_body: &mut physics::RigidBody,
+        b_body: &mut physics::RigidBody,
+    ) {
+        self.angular
+            .prepare(dt, settings, &basis_bivectors(), a_body, b_body);
+
+        let (a_arm, b_arm, axis, separation) = self.arms(a_body, b_body);
+        let basis = a_body.orientation.to_matrix() * self.a_basis;
+
+        // Only the directions perpendicular to the axis are constrained
+        for (i, row) in self.linear.iter_mut().enumerate() {
+            let direction = basis.column(i + 1).into_owned();
+            row.set_linear(direction, a_arm, b_arm);
+            row.prepare(dt, settings, separation.dot(&direction), a_body, b_body);
+        }
+
+        // The offset grows as b moves along the axis relative to a
+        self.drive_row.set_linear(-axis, a_arm, b_arm);
+        let offset = self.offset(a_body, b_body);
+        self.drive
+            .prepare(dt, settings, &self.drive_row, offset, a_body, b_body);
+    }
+
+    fn apply(&mut self, a_body: &mut physics::RigidBody, b_body: &mut physics::RigidBody) {
+        self.drive.apply(a_body, b_body);
+        self.angular.apply(a_body, b_body);
+        for row in self.linear.iter_mut() {
+            row.apply(a_body, b_body);
+        }
+    }
+
+    fn apply_position(&mut self, a_body: &mut physics::RigidBody, b_body: &mut physics::RigidBody) {
+        self.drive.apply_position(a_body, b_body);
+        self.angular.apply_position(a_body, b_body);
+        for row in self.linear.iter_mut() {
+            row.apply_position(a_body, b_body);
+        }
+    }
+
+    fn impulse(&self) -> (f32, f32) {
+        total_impulse(
+            self.angular
+                .rows
+                .iter()
+                .chain(&self.linear)
+                .chain(self.drive.rows()),
+            (0.0, 0.0),
+        )
+    }
+}
+
+// Keeps the distance between two anchor points within a range. With a minimum
+// length of zero, this behaves like a rope.
+pub struct DistanceJoint {
+    a_anchor: na::Vector4,
+    b_anchor: na::Vector4,
+    pub min_length: f32,
+    pub max_length: f32,
+
+    lower: Row,
+    upper: Row,
+}
+
+impl DistanceJoint {
+    // Anchors are world-space points
+    pub fn new(
+        a_body: &physics::RigidBody,
+        b_body: &physics::RigidBody,
+        a_anchor: na::Vector4,
+        b_anchor: na::Vector4,
+        min_length: f32,
+        max_length: f32,
+    ) -> Self {
+        Self {
+            a_anchor: to_local(a_body, a_anchor),
+            b_anchor: to_local(b_body, b_anchor),
+            min_length,
+            max_length,
+
+            // The lower limit can only push the anchors apart, and the upper one
+            // only pull them together
+            lower: Row {
+                lower: 0.0,
+                ..Default::default()
+            },
+            upper: Row {
+                upper: 0.0,
+                ..Default::default()
+            },
+        }
+    }
+}
+
+// The arms and direction between the anchors of a joint, and the distance
+// between them
+fn separation(
+    a_anchor: na::Vector4,
+    b_anchor: na::Vector4,
+    a_body: &physics::RigidBody,
+    b_body: &physics::RigidBody,
+) -> (na::Vector4, na::Vector4, na::Vector4, f32) {
+    let a_arm = a_body.orientation.to_matrix() * a_anchor;
+    let b_arm = b_body.orientation.to_matrix() * b_anchor;
+    let separation = (a_body.position + a_arm) - (b_body.position + b_arm);
+    let length = separation.norm();
+    let direction = if length > 0.0 {
+        separation / length
+    } else {
+        na::vec4(0.0, 1.0, 0.0, 0.0)
+    };
+    (a_arm, b_arm, direction, length)
+}
+
+impl Joint for DistanceJoint {
+    fn prepare(
+        &mut self,
+        dt: f32,
+        settings: &physics::SolverSettings,
+        a_body: &mut physics::RigidBody,
+        b_body: &mut physics::RigidBody,
+    ) {
+        let (a_arm, b_arm, direction, length) =
+            separation(self.a_anchor, self.b_anchor, a_body, b_body);
+
+        let lower_error = length - self.min_length;
+        let upper_error = length - self.max_length;
+        for (row, error, within) in [
+            (&mut self.lower, lower_error, lower_error > 0.0),
+            (&mut self.upper, upper_error, upper_error < 0.0),
+        ] {
+            row.set_linear(direction, a_arm, b_arm);
+            row.prepare_limit(dt, settings, error, within, a_body, b_body);
+        }
+    }
+
+    fn apply(&mut self, a_body: &mut physics::RigidBody, b_body: &mut physics::RigidBody) {
+        self.lower.apply(a_body, b_body);
+        self.upper.apply(a_body, b_body);
+    }
+
+    fn apply_position(&mut self, a_body: &mut physics::RigidBody, b_body: &mut physics::RigidBody) {
+        self.lower.apply_position(a_body, b_body);
+        self.upper.apply_position(a_body, b_body);
+    }
+
+    fn impulse(&self) -> (f32, f32) {
+        total_impulse([&self.lower, &self.upper], (0.0, 0.0))
+    }
+}
+
+// A damped spring between two anchor points
+pub struct SpringJoint {
+    a_anchor: na::Vector4,
+    b_anchor: na::Vector4,
+    pub rest_length: f32,
+    pub stiffness: f32,
+    pub damping: f32,
+
+    row: Row,
+}
+
+impl SpringJoint {
+    // Anchors are world-space points, and the rest length is the current distance
+    // between them
+    pub fn new(
+        a_body: &physics::RigidBody,
+        b_body: &physics::RigidBody,
+        a_anchor: na::Vector4,
+        b_anchor: na::Vector4,
+        stiffness: f32,
+        damping: f32,
+    ) -> Self {
+        Self {
+            a_anchor: to_local(a_body, a_anchor),
+            b_anchor: to_local(b_body, b_anchor),
+            rest_length: (a_anchor - b_anchor).norm(),
+            stiffness,
+            damping,
+
+            row: Row::default(),
+        }
+    }
+}
+
+impl Joint for SpringJoint {
+    fn prepare(
+        &mut self,
+        dt: f32,
+        settings: &physics::SolverSettings,
+        a_body: &mut physics::RigidBody,
+        b_body: &mut physics::RigidBody,
+    ) {
+        let (a_arm, b_arm, direction, length) =
+            separation(self.a_anchor, self.b_anchor, a_body, b_body);
+        self.row.set_linear(direction, a_arm, b_arm);
+        self.row.prepare_spring(
+            dt,
+            length - self.rest_length,
+            self.stiffness,
+            self.damping,
+            a_body,
+            b_body,
+        );
+        self.row.warm_start(settings, a_body, b_body);
+    }
+
+    fn apply(&mut self, a_body: &mut physics::RigidBody, b_body: &mut physics::RigidBody) {
+        self.row.apply(a_body, b_body);
+    }
+
+    // Springs aren't errors to be corrected, so there's nothing to do here
+    fn apply_position(
+        &mut self,
+        _a_body: &mut physics::RigidBody,
+        _b_body: &mut physics::RigidBody,
+    ) {
+    }
+
+    fn impulse(&self) -> (f32, f32) {
+        total_impulse([&self.row], (0.0, 0.0))
+    }
+}
+
+// Spring settings for a GrabJoint. The limits are on the magnitudes of the whole
+// force and torque, whichever way they point.
+#[derive(Debug, Clone, Copy)]
+pub struct GrabSpring {
+    pub stiffness: f32,
+    pub damping: f32,
+    pub max_force: f32,
+
+    pub angular_stiffness: f32,
+    pub angular_damping: f32,
+    pub max_torque: f32,
+
+    // How far the held point may lag behind before the grab breaks
+    pub break_distance: f32,
+}
+
+impl Default for GrabSpring {
+    fn default() -> Self {
+        Self {
+            stiffness: 200.0,
+            damping: 20.0,
+            max_force: 500.0,
+
+            angular_stiffness: 50.0,
+            angular_damping: 5.0,
+            max_torque: 100.0,
+
+            break_distance: 3.0,
+        }
+    }
+}
+
+// Holds a point on b at some distance in front of a, and b's orientation fixed
+// relative to a's, using damped springs. Only b is moved - a behaves as though it
+// were immovable, so holding something heavy never drags a around.
+pub struct GrabJoint {
+    // Direction of the held point in a's local space
+    a_direction: na::Vector4,
+    b_anchor: na::Vector4,
+    reference: ga::Rotor4,
+    pub distance: f32,
+    pub spring: GrabSpring,
+
+    linear: [Row; 4],
+    angular: [Row; 6],
+    max_linear_impulse: f32,
+    max_angular_impulse: f32,
+    // Current distance between the held point and where it should be
+    stretch: f32,
+}
+
+// Scale down the accumulated impulses of a set of rows, taking back the excess,
+// so that together they're no larger than max_impulse
+fn clamp_impulses(
+    rows: &mut [Row],
+    max_impulse: f32,
+    a_body: &mut physics::RigidBody,
+    b_body: &mut physics::RigidBody,
+) {
+    let magnitude = rows
+        .iter()
+        .map(|row| row.impulse * row.impulse)
+        .sum::<f32>()
+        .sqrt();
+    if magnitude <= max_impulse {
+        return;
+    }
+    for row in rows.iter_mut() {
+        let impulse = row.impulse * max_impulse / magnitude;
+        row.apply_impulse(impulse - row.impulse, a_body, b_body);
+        row.impulse = impulse;
+    }
+}
+
+// A copy of a body which the solver can't move
+fn immovable(body: &physics::RigidBody) -> physics::RigidBody {
+    physics::RigidBody {
+        inverse_mass: 0.0,
+        inverse_inertia_tensor: na::Matrix6::zeros(),
+        ..*body
+    }
+}
+
+impl GrabJoint {
+    // The anchor is the world-space point on b to hold, which is held at its
+    // current position relative to a
+    pub fn new(
+        a_body: &physics::RigidBody,
+        b_body: &physics::RigidBody,
+        anchor: na::Vector4,
+        spring: GrabSpring,
+    ) -> Self {
+        let a_anchor = a_body.orientation.to_matrix().transpose() * (anchor - a_body.position);
+        let distance = a_anchor.norm();
+        Self {
+            a_direction: if distance > 0.0 {
+                a_anchor / distance
+            } else {
+                na::vec4(0.0, 0.0, 1.0, 0.0)
+            },
+            b_anchor: to_local(b_body, anchor),
+            reference: relative_orientation(a_body, b_body),
+            distance,
+            spring,
+
+            linear: std::array::from_fn(|_| Row::default()),
+            angular: std::array::from_fn(|_| Row::default()),
+            max_linear_impulse: 0.0,
+            max_angular_impulse: 0.0,
+            stretch: 0.0,
+        }
+    }
+
+    // Turn the held orientation by a world-space rotation
+    pub fn rotate(&mut self, rotation: ga::Bivector4, a_body: &physics::RigidBody) {
+        self.reference = self.reference
+            * a_body.orientation
+            * ga::Rotor4::from_bivector(rotation)
+            * a_body.orientation.reverse();
+    }
+}
+
+impl Joint for GrabJoint {
+    fn prepare(
+        &mut self,
+        dt: f32,
+        settings: &physics::SolverSettings,
+        a_body: &mut physics::RigidBody,
+        b_body: &mut physics::RigidBody,
+    ) {
+        let mut a_body = immovable(a_body);
+
+        let a_arm = a_body.orientation.to_matrix() * self.a_direction * self.distance;
+        let b_arm = b_body.orientation.to_matrix() * self.b_anchor;
+        let separation = (a_body.position + a_arm) - (b_body.position + b_arm);
+        self.stretch = separation.norm();
+
+        self.max_linear_impulse = self.spring.max_force * dt;
+        for (i, row) in self.linear.iter_mut().enumerate() {
+            row.set_linear(na::Vector4::ith(i, 1.0), a_arm, b_arm);
+            row.prepare_spring(
+                dt,
+                separation[i],
+                self.spring.stiffness,
+                self.spring.damping,
+                &a_body,
+                b_body,
+            );
+            row.warm_start(settings, &mut a_body, b_body);
+        }
+
+        let error = angular_error(self.reference, &a_body, b_body).as_vector();
+        self.max_angular_impulse = self.spring.max_torque * dt;
+        for (row, plane) in self.angular.iter_mut().zip(basis_bivectors()) {
+            row.set_angular(plane);
+            row.prepare_spring(
+                dt,
+                -error.dot(&plane.as_vector()),
+                self.spring.angular_stiffness,
+                self.spring.angular_damping,
+                &a_body,
+                b_body,
+            );
+            row.warm_start(settings, &mut a_body, b_body);
+        }
+    }
+
+    fn apply(&mut self, a_body: &mut physics::RigidBody, b_body: &mut physics::RigidBody) {
+        let mut a_body = immovable(a_body);
+        for row in self.linear.iter_mut().chain(self.angular.iter_mut()) {
+            row.apply(&mut a_body, b_body);
+        }
+        clamp_impulses(
+            &mut self.linear,
+            self.max_linear_impulse,
+            &mut a_body,
+            b_body,
+        );
+        clamp_impulses(
+            &mut self.angular,
+            self.max_angular_impulse,
+            &mut a_body,
+            b_body,
+        );
+    }
+
+    // Springs aren't errors to be corrected, so there's nothing to do here
+    fn apply_position(
+        &mut self,
+        _a_body: &mut physics::RigidBody,
+        _b_body: &mut physics::RigidBody,
+    ) {
+    }
+
+    fn impulse(&self) -> (f32, f32) {
+        total_impulse(self.linear.iter().chain(&self.angular), (0.0, 0.0))
+    }
+
+    fn is_broken(&self) -> bool {
+        self.stretch > self.spring.break_distance
+    }
+}
+
+// Wraps another joint, which fails if it has to apply more than the given
+// impulses in a single step. The thresholds are impulses rather than forces, so
+// they depend on the timestep.
+pub struct Breakable<J: Joint> {
+    pub joint: J,
+    pub max_linear_impulse: f32,
+    pub max_angular_impulse: f32,
+}
+
+impl<J: Joint> Breakable<J> {
+    pub fn new(joint: J, max_linear_impulse: f32, max_angular_impulse: f32) -> Self {
+        Self {
+            joint,
+            max_linear_impulse,
+            max_angular_impulse,
+        }
+    }
+}
+
+impl<J: Joint + 'static> Joint for Breakable<J> {
+    fn prepare(
+        &mut self,
+        dt: f32,
+        settings: &physics::SolverSettings,
+        a_body: &mut physics::RigidBody,
+        b_body: &mut physics::RigidBody,
+    ) {
+        self.joint.prepare(dt, settings, a_body, b_body);
+    }
+
+    fn apply(&mut self, a_body: &mut physics::RigidBody, b_body: &mut physics::RigidBody) {
+        self.joint.apply(a_body, b_body);
+    }
+
+    fn apply_position(&mut self, a_body: &mut physics::RigidBody, b_body: &mut physics::RigidBody) {
+        self.joint.apply_position(a_body, b_body);
+    }
+
+    fn impulse(&self) -> (f32, f32) {
+        self.joint.impulse()
+    }
+
+    fn is_broken(&self) -> bool {
+        let (linear, angular) = self.joint.impulse();
+        linear > self.max_linear_impulse || angular > self.max_angular_impulse
+    }
 }
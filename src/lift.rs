@@ -0,0 +1,240 @@
+use crate::mesh;
+use crate::na;
+use crate::na::vec4;
+
+use std::f32::consts::TAU;
+
+// A closed triangle mesh in 3D, to be lifted into 4D. Triangles are wound
+// counter-clockwise when seen from outside.
+#[derive(Debug, Clone)]
+pub struct Mesh3 {
+    pub vertices: Vec<na::Vector3>,
+    pub triangles: Vec<[u32; 3]>,
+}
+
+impl Mesh3 {
+    pub fn icosahedron() -> Self {
+        let phi = (1.0 + 5.0f32.sqrt()) / 2.0;
+        let vertices = [
+            (-1.0, phi, 0.0),
+            (1.0, phi, 0.0),
+            (-1.0, -phi, 0.0),
+            (1.0, -phi, 0.0),
+            (0.0, -1.0, phi),
+            (0.0, 1.0, phi),
+            (0.0, -1.0, -phi),
+            (0.0, 1.0, -phi),
+            (phi, 0.0, -1.0),
+            (phi, 0.0, 1.0),
+            (-phi, 0.0, -1.0),
+            (-phi, 0.0, 1.0),
+        ]
+        .iter()
+        .map(|&(x, y, z)| na::Vector3::new(x, y, z).normalize() * 0.5)
+        .collect();
+        #[rustfmt::skip]
+        let triangles = vec![
+            [0, 11, 5], [0, 5, 1], [0, 1, 7], [0, 7, 10], [0, 10, 11],
+            [1, 5, 9], [5, 11, 4], [11, 10, 2], [10, 7, 6], [7, 1, 8],
+            [3, 9, 4], [3, 4, 2], [3, 2, 6], [3, 6, 8], [3, 8, 9],
+            [4, 9, 5], [2, 4, 11], [6, 2, 10], [8, 6, 7], [9, 8, 1],
+        ];
+        Self {
+            vertices,
+            triangles,
+        }
+    }
+
+    fn corners(&self, triangle: &[u32; 3]) -> [na::Vector3; 3] {
+        triangle.map(|i| self.vertices[i as usize])
+    }
+
+    fn normal(&self, triangle: &[u32; 3]) -> na::Vector3 {
+        let [a, b, c] = self.corners(triangle);
+        (b - a).cross(&(c - a)).normalize()
+    }
+}
+
+// Builds the mesh a cell at a time. Each cell's vertices get their own copies,
+// so that it can be flat shaded in its own color.
+struct Cells {
+    count: usize,
+    vertices: Vec<na::Vector4>,
+    normals: Vec<na::Vector4>,
+    colors: Vec<na::Vector4>,
+    indices: Vec<u32>,
+}
+
+impl Cells {
+    fn new() -> Self {
+        Self {
+            count: 0,
+            vertices: Vec::new(),
+            normals: Vec::new(),
+            colors: Vec::new(),
+            indices: Vec::new(),
+        }
+    }
+
+    // Add a cell, given as tetrahedra indexing into its vertices. The tetrahedra
+    // are wound to face along the normal, and any which are flat are dropped.
+    fn add(&mut self, vertices: &[na::Vector4], tetrahedra: &[[usize; 4]], normal: na::Vector4) {
+        let color = mesh::palette(self.count);
+        self.count += 1;
+        let base = self.vertices.len() as u32;
+        for tetrahedron in tetrahedra {
+            let mut tetrahedron = *tetrahedron;
+            let cell_normal = mesh::tetrahedron_normal(&tetrahedron.map(|i| vertices[i]));
+            if cell_normal.norm() < 1e-9 {
+                continue;
+            }
+            if cell_normal.dot(&normal) < 0.0 {
+                tetrahedron.swap(2, 3);
+            }
+            self.indices.extend(tetrahedron.map(|i| base + i as u32));
+        }
+        self.vertices.extend_from_slice(vertices);
+        self.normals
+            .extend(itertools::repeat_n(normal, vertices.len()));
+        self.colors
+            .extend(itertools::repeat_n(color, vertices.len()));
+    }
+
+    fn build(self) -> mesh::Mesh4 {
+        mesh::Mesh4::new(self.vertices, self.normals, self.colors, self.indices)
+    }
+}
+
+// The three tetrahedra filling a triangular prism, whose bottom corners are
+// 0, 1, 2 and top corners 3, 4, 5. Neighbouring prisms have to split their
+// shared side the same way, so each side's diagonal runs from the bottom of
+// the corner with the lower id to the top of the other.
+fn prism(ids: [u32; 3]) -> [[usize; 4]; 3] {
+    let mut order = [0, 1, 2];
+    order.sort_by_key(|&i| ids[i]);
+    let [p, q, r] = order;
+    [
+        [p, q, r, r + 3],
+        [p, q, q + 3, r + 3],
+        [p, p + 3, q + 3, r + 3],
+    ]
+}
+
+impl mesh::Mesh4 {
+    // Sweep a 3D mesh along w, from -length / 2 to length / 2. The ends are
+    // filled in with tetrahedra from the centre of the mesh to each triangle, so
+    // the mesh has to be star-shaped around its centre (convex is enough).
+    pub fn extrude(mesh: &Mesh3, length: f32) -> Self {
+        let half = length / 2.0;
+        let lift = |v: na::Vector3, w: f32| vec4(v[0], v[1], v[2], w);
+        let centre = mesh.vertices.iter().sum::<na::Vector3>() / mesh.vertices.len() as f32;
+
+        let mut cells = Cells::new();
+        for triangle in mesh.triangles.iter() {
+            let corners = mesh.corners(triangle);
+            let vertices: Vec<na::Vector4> = [-half, half]
+                .iter()
+                .flat_map(|&w| corners.map(|v| lift(v, w)))
+                .collect();
+            cells.add(
+                &vertices,
+                &prism(*triangle),
+                lift(mesh.normal(triangle), 0.0),
+            );
+        }
+        for w in [-half, half] {
+            let mut vertices = vec![lift(centre, w)];
+            let mut tetrahedra = Vec::new();
+            for triangle in mesh.triangles.iter() {
+                let i = vertices.len();
+                vertices.extend(mesh.corners(triangle).map(|v| lift(v, w)));
+                tetrahedra.push([0, i, i + 1, i + 2]);
+            }
+            cells.add(&vertices, &tetrahedra, vec4(0.0, 0.0, 0.0, w.signum()));
+        }
+        cells.build()
+    }
+
+    // Spin a 3D mesh in the xw plane, around the yz plane. The mesh should be on
+    // the positive x side - a shape touching the plane closes up like a glome, and
+    // one away from it makes a hole like a torus. Each triangle is swept through
+    // every step of the spin, and each step is a cell with the normal from the
+    // middle of it, so the normals are flat per segment as well as across the
+    // original triangles.
+    pub fn revolve(mesh: &Mesh3, segments: usize) -> Self {
+        let spin =
+            |v: na::Vector3, angle: f32| vec4(v[0] * angle.cos(), v[1], v[2], v[0] * angle.sin());
+
+        let mut cells = Cells::new();
+        for triangle in mesh.triangles.iter() {
+            let corners = mesh.corners(triangle);
+            let normal = mesh.normal(triangle);
+            for segment in 0..segments {
+                let angles = [segment, segment + 1].map(|x| x as f32 * TAU / segments as f32);
+                let vertices: Vec<na::Vector4> = angles
+                    .iter()
+                    .flat_map(|&angle| corners.map(|v| spin(v, angle)))
+                    .collect();
+                cells.add(
+                    &vertices,
+                    &prism(*triangle),
+                    spin(normal, (angles[0] + angles[1]) / 2.0),
+                );
+            }
+        }
+        cells.build()
+    }
+
+    // The Cartesian product of two convex polygons, one in the xy plane and one in
+    // zw, with their corners counter-clockwise. Its cells are prisms - each edge of
+    // one polygon swept over the whole of the other.
+    pub fn duoprism(a: &[na::Vector2], b: &[na::Vector2]) -> Self {
+        let mut cells = Cells::new();
+        for (first, edges, other) in [(true, a, b), (false, b, a)] {
+            let place = |edge_point: na::Vector2, other_point: na::Vector2| {
+                if first {
+                    vec4(edge_point[0], edge_point[1], other_point[0], other_point[1])
+                } else {
+                    vec4(other_point[0], other_point[1], edge_point[0], edge_point[1])
+                }
+            };
+            for i in 0..edges.len() {
+                let (start, end) = (edges[i], edges[(i + 1) % edges.len()]);
+                // Sweep from the corner with the lower id, so that the sides shared
+                // with the other polygon's cells are split the same way
+                let (low, high) = if i == edges.len() - 1 {
+                    (end, start)
+                } else {
+                    (start, end)
+                };
+                let vertices: Vec<na::Vector4> = [low, high]
+                    .iter()
+                    .flat_map(|&e| other.iter().map(move |&o| place(e, o)))
+                    .collect();
+                // Fan the other polygon into triangles, and sweep each one along the
+                // edge
+                let n = other.len();
+                let tetrahedra: Vec<[usize; 4]> = (1..n - 1)
+                    .flat_map(|k| {
+                        let triangle = [0, k, k + 1];
+                        prism(triangle.map(|x| x as u32))
+                            .map(|tetrahedron| tetrahedron.map(|x| triangle[x % 3] + x / 3 * n))
+                    })
+                    .collect();
+                let outward = na::Vector2::new(end[1] - start[1], start[0] - end[0]).normalize();
+                cells.add(&vertices, &tetrahedra, place(outward, na::Vector2::zeros()));
+            }
+        }
+        cells.build()
+    }
+}
+
+// The corners of a regular polygon, counter-clockwise
+pub fn regular_polygon(sides: usize, radius: f32) -> Vec<na::Vector2> {
+    (0..sides)
+        .map(|i| {
+            let angle = i as f32 * TAU / sides as f32;
+            na::Vector2::new(angle.cos(), angle.sin()) * radius
+        })
+        .collect()
+}
@@ -2,18 +2,32 @@ mod actor;
 mod collision;
 mod constraints;
 mod contact;
+mod csg;
+mod despawn;
+mod diagnostics;
 mod draw_state;
+mod forces;
 mod ga;
 mod gjk;
+mod gravity;
 mod input;
 mod joint;
+mod lift;
 mod mesh;
+mod mesh_builder;
+mod mesh_file;
 mod mesh_renderer;
 mod mpr;
 mod na;
 mod physics;
+mod polytope;
+mod raycast;
 mod renderer;
+mod sdf;
+mod simplify;
 mod sprite_renderer;
+mod stepper;
+mod tessellate;
 mod texture;
 mod wgputil;
 
@@ -29,22 +43,31 @@ async fn run(
     event_loop: EventLoop<()>,
     window: winit::window::Window,
     mut world: hecs::World,
+    mut constraints: constraints::Constraints,
     player_entity: hecs::Entity,
 ) {
     let mut renderer = renderer::Renderer::new(&window);
-    let mut constraints = constraints::Constraints::new();
     let mut input_state = input::InputState::default();
 
     let mut cursor_mode = winit::window::CursorGrabMode::None;
     window.set_cursor_grab(cursor_mode).unwrap();
 
-    let dt: f32 = 1.0 / 120.0;
-    let mut remaining: f32 = 0.0;
+    // The demo resolves penetration in a separate pass, which keeps the stacks
+    // from bouncing, and lets spinning bodies tumble
+    let mut solver_settings = physics::SolverSettings {
+        position_correction: physics::PositionCorrection::SplitImpulse,
+        gyroscopic_torque: physics::GyroscopicTorque::Implicit,
+        ..Default::default()
+    };
+    let mut world_gravity = gravity::Gravity::default();
+    let mut stepper = stepper::Stepper::new(8);
+    // Log diagnostics this often while they're turned on
+    let mut diagnostics_interval: Option<f32> = None;
+    let mut diagnostics_timer: f32 = 0.0;
     let mut last_frame_time = instant::Instant::now();
 
     event_loop.run(move |event, _, control_flow| {
         match event {
-            Event::NewEvents(_) => input_state.new_events(),
             Event::WindowEvent {
                 ref event,
                 window_id,
@@ -81,12 +104,159 @@ async fn run(
                             };
                             window.set_cursor_grab(cursor_mode).unwrap();
                         }
+                        // Switch "down" between the y and w axes
+                        VirtualKeyCode::G if pressed => {
+                            world_gravity.vector = na::vec4(
+                                0.0,
+                                world_gravity.vector[3],
+                                0.0,
+                                world_gravity.vector[1],
+                            );
+                            log::info!("gravity: {:?}", world_gravity.vector.as_slice());
+                        }
+                        VirtualKeyCode::I if pressed => {
+                            solver_settings.integrator = match solver_settings.integrator {
+                                physics::Integrator::SymplecticEuler => {
+                                    physics::Integrator::VelocityVerlet
+                                }
+                                physics::Integrator::VelocityVerlet => {
+                                    physics::Integrator::RungeKutta4
+                                }
+                                physics::Integrator::RungeKutta4 => {
+                                    physics::Integrator::SymplecticEuler
+                                }
+                            };
+                            log::info!("integrator: {:?}", solver_settings.integrator);
+                        }
+                        VirtualKeyCode::T if pressed => {
+                            solver_settings.gyroscopic_torque =
+                                match solver_settings.gyroscopic_torque {
+                                    physics::GyroscopicTorque::None => {
+                                        physics::GyroscopicTorque::Explicit
+                                    }
+                                    physics::GyroscopicTorque::Explicit => {
+                                        physics::GyroscopicTorque::Implicit
+                                    }
+                                    physics::GyroscopicTorque::Implicit => {
+                                        physics::GyroscopicTorque::None
+                                    }
+                                };
+                            log::info!(
+                                "gyroscopic torque: {:?}",
+                                solver_settings.gyroscopic_torque
+                            );
+                        }
+                        // Remove whichever dynamic body is in the middle of the screen
+                        VirtualKeyCode::Delete if pressed => {
+                            let (position, forward) = world
+                                .get::<&physics::RigidBody>(player_entity)
+                                .map(|x| (x.position, x.orientation.to_matrix().column(2).normalize()))
+                                .unwrap();
+                            if let Some((entity, _)) = collision::cast_ray(position, forward, &world)
+                            {
+                                let dynamic = world
+                                    .get::<&physics::RigidBody>(entity)
+                                    .map(|x| x.is_dynamic())
+                                    .unwrap_or(false);
+                                if dynamic {
+                                    despawn::despawn(&mut constraints, &mut world, entity).unwrap();
+                                }
+                            }
+                        }
+                        // Save the mesh of whatever is in the middle of the screen
+                        #[cfg(not(target_arch = "wasm32"))]
+                        VirtualKeyCode::X if pressed => {
+                            let (position, forward) = world
+                                .get::<&physics::RigidBody>(player_entity)
+                                .map(|x| (x.position, x.orientation.to_matrix().column(2).normalize()))
+                                .unwrap();
+                            if let Some((entity, _)) = collision::cast_ray(position, forward, &world)
+                            {
+                                if let Ok(mesh) = world.get::<&mesh::Mesh4>(entity) {
+                                    let path = format!("mesh-{}.off4", entity.id());
+                                    match mesh_file::save(&mesh, &path) {
+                                        Ok(()) => log::info!("saved {}", path),
+                                        Err(error) => log::error!("couldn't save {}: {}", path, error),
+                                    }
+                                }
+                            }
+                        }
+                        // Paint a spot onto the mesh in the middle of the screen, in
+                        // a color to stand out from where it landed
+                        VirtualKeyCode::V if pressed => {
+                            let (position, forward) = world
+                                .get::<&physics::RigidBody>(player_entity)
+                                .map(|x| (x.position, x.orientation.to_matrix().column(2).normalize()))
+                                .unwrap();
+                            if let Some((entity, _, Some(hit))) = collision::pick(position, forward, &world) {
+                                log::info!(
+                                    "painting cell {} at {:?}, facing {:?}",
+                                    hit.cell,
+                                    hit.position,
+                                    hit.normal
+                                );
+                                let color = na::Vector4::repeat(1.0) - hit.color + na::vec4(0.0, 0.0, 0.0, 1.0);
+                                if let Ok((index, mesh, lod)) = world.query_one_mut::<(
+                                    &raycast::MeshIndex,
+                                    &mut mesh::Mesh4,
+                                    Option<&mut mesh_renderer::Lod>,
+                                )>(entity)
+                                {
+                                    index.paint(mesh, &hit, 0.15, color);
+                                    if let Some(lod) = lod {
+                                        lod.update(mesh);
+                                    }
+                                }
+                                // Upload the mesh again
+                                if let Ok(mesh_buffers) =
+                                    world.remove_one::<mesh_renderer::MeshBuffers>(entity)
+                                {
+                                    mesh_buffers.destroy();
+                                }
+                            }
+                        }
+                        VirtualKeyCode::O if pressed => {
+                            diagnostics_interval = match diagnostics_interval {
+                                Some(_) => None,
+                                None => Some(1.0),
+                            };
+                            diagnostics_timer = 0.0;
+                        }
+                        VirtualKeyCode::LBracket | VirtualKeyCode::RBracket if pressed => {
+                            solver_settings.substeps = if *keycode == VirtualKeyCode::LBracket {
+                                (solver_settings.substeps - 1).max(1)
+                            } else {
+                                solver_settings.substeps + 1
+                            };
+                            log::info!("substeps: {}", solver_settings.substeps);
+                        }
+                        VirtualKeyCode::P if pressed => {
+                            solver_settings.position_correction =
+                                match solver_settings.position_correction {
+                                    physics::PositionCorrection::Baumgarte => {
+                                        physics::PositionCorrection::SplitImpulse
+                                    }
+                                    physics::PositionCorrection::SplitImpulse => {
+                                        physics::PositionCorrection::Baumgarte
+                                    }
+                                };
+                            log::info!(
+                                "position correction: {:?}",
+                                solver_settings.position_correction
+                            );
+                        }
                         _ => {}
                     }
                 }
                 WindowEvent::MouseInput { button, state, .. } => {
                     input_state.mouse_click(*button, *state == ElementState::Pressed);
                 }
+                WindowEvent::MouseWheel { delta, .. } => {
+                    input_state.mouse_scrolled(match delta {
+                        MouseScrollDelta::LineDelta(_, y) => *y,
+                        MouseScrollDelta::PixelDelta(position) => position.y as f32 / 100.0,
+                    });
+                }
                 _ => {}
             },
             Event::DeviceEvent {
@@ -104,16 +274,59 @@ async fn run(
                 let frame_start_time = instant::Instant::now();
                 let elapsed = frame_start_time - last_frame_time;
                 last_frame_time = frame_start_time;
-                remaining += (elapsed.as_nanos() as f64 / 1e9) as f32;
+                let steps = stepper.advance(
+                    (elapsed.as_nanos() as f64 / 1e9) as f32,
+                    solver_settings.dt,
+                );
 
-                while remaining > 0.0 {
+                for _ in 0..steps {
+                    stepper::save_poses(&mut world);
                     actor::update_actor(&mut constraints, &mut world, &input_state, player_entity);
-                    collision::do_collisions(&mut constraints, &mut world);
-                    physics::apply_physics(dt, &mut constraints, &mut world);
-                    remaining -= dt;
+                    physics::step(
+                        &solver_settings,
+                        &world_gravity,
+                        &mut constraints,
+                        &mut world,
+                    );
+                    for broken in constraints.take_broken_joints() {
+                        log::info!("joint broken: {:?}", broken);
+                        actor::joint_broken(&mut world, player_entity, &broken);
+                    }
+
+                    if let Some(interval) = diagnostics_interval {
+                        diagnostics_timer -= solver_settings.dt;
+                        if diagnostics_timer <= 0.0 {
+                            diagnostics_timer += interval;
+                            let d = diagnostics::measure(
+                                &world_gravity,
+                                &constraints,
+                                &world,
+                                na::Vector4::zeros(),
+                            );
+                            log::info!(
+                                "energy: {:.3} (translational {:.3}, rotational {:.3}, potential {:.3}), momentum: {:.3} / {:.3}, max penetration: {:.4}",
+                                d.total_energy(),
+                                d.translational_energy,
+                                d.rotational_energy,
+                                d.potential_energy,
+                                d.linear_momentum.norm(),
+                                d.angular_momentum.norm(),
+                                d.max_penetration,
+                            );
+                        }
+                    }
                 }
 
-                renderer.update_buffers(&mut world, player_entity);
+                // Mouse movement is kept until there's a step to use it
+                if steps > 0 {
+                    input_state.new_events();
+                }
+
+                renderer.update_buffers(
+                    &mut world,
+                    player_entity,
+                    stepper.alpha(solver_settings.dt),
+                );
                 match renderer.render(&world) {
                     Ok(_) => {}
                     Err(wgpu::SurfaceError::Lost) => renderer.resize(renderer.size),
@@ -131,14 +344,575 @@ async fn run(
     });
 }
 
-fn build_world() -> (hecs::Entity, hecs::World) {
+fn spawn_box(
+    world: &mut hecs::World,
+    body: physics::RigidBody,
+    scale: na::Vector4,
+) -> hecs::Entity {
+    let mesh = mesh::Mesh4::cube().transformed(&na::Affine4::from_pos(
+        na::Vector4::zeros(),
+        na::Matrix4::identity(),
+        scale,
+    ));
+    spawn_mesh(world, body, mesh)
+}
+
+fn spawn_mesh(
+    world: &mut hecs::World,
+    body: physics::RigidBody,
+    mesh: mesh::Mesh4,
+) -> hecs::Entity {
+    world.spawn((
+        body,
+        collision::Collider::from_mesh4(&mesh),
+        raycast::MeshIndex::new(&mesh),
+        mesh,
+        draw_state::DrawState {
+            contacts: 0,
+            hollow: false,
+        },
+    ))
+}
+
+// A few bodies connected by each kind of joint
+fn build_joints(
+    world: &mut hecs::World,
+    constraints: &mut constraints::Constraints,
+    floor: (hecs::Entity, physics::RigidBody),
+) {
+    // Something to hang things from
+    let beam_body = physics::RigidBody {
+        position: na::vec4(-5.0, 2.0, 0.0, 0.0),
+        ..Default::default()
+    }
+    .with_body_type(physics::BodyType::Static);
+    let beam = spawn_box(world, beam_body, na::vec4(3.0, 0.2, 0.2, 0.2));
+
+    // A box on a rope, and a box on a spring
+    let rope_body = physics::RigidBody {
+        position: na::vec4(-4.0, 0.5, 0.0, 0.0),
+        ..Default::default()
+    };
+    let rope = spawn_box(world, rope_body, na::vec4(0.5, 0.5, 0.5, 0.5));
+    constraints.add_joint(
+        beam,
+        rope,
+        joint::DistanceJoint::new(
+            &beam_body,
+            &rope_body,
+            na::vec4(-4.0, 1.9, 0.0, 0.0),
+            na::vec4(-4.0, 0.75, 0.0, 0.0),
+            0.0,
+            1.5,
+        ),
+    );
+    let spring_body = physics::RigidBody {
+        position: na::vec4(-6.0, 0.5, 0.0, 0.0),
+        ..Default::default()
+    };
+    let spring = spawn_box(world, spring_body, na::vec4(0.5, 0.5, 0.5, 0.5));
+    constraints.add_joint(
+        beam,
+        spring,
+        joint::SpringJoint::new(
+            &beam_body,
+            &spring_body,
+            na::vec4(-6.0, 1.9, 0.0, 0.0),
+            na::vec4(-6.0, 0.75, 0.0, 0.0),
+            100.0,
+            2.0,
+        ),
+    );
+
+    // A flap hinged to the floor, free to rotate in the yz plane, which a motor
+    // lifts part of the way open
+    let (floor, floor_body) = floor;
+    let flap_body = physics::RigidBody {
+        position: na::vec4(-2.0, -1.85, 3.0, 0.0),
+        ..Default::default()
+    };
+    let flap = spawn_box(world, flap_body, na::vec4(1.0, 0.3, 1.0, 1.0));
+    constraints.add_joint(
+        floor,
+        flap,
+        joint::HingeJoint::new(
+            &floor_body,
+            &flap_body,
+            na::vec4(-2.0, -1.85, 2.5, 0.0),
+            na::vec4(0.0, 1.0, 0.0, 0.0).wedge(na::vec4(0.0, 0.0, 1.0, 0.0)),
+        )
+        .with_limits(-2.0, 0.0)
+        .with_motor(joint::Motor::Position {
+            target: -1.0,
+            max_speed: 1.0,
+            max_impulse: 0.5,
+        }),
+    );
+
+    // A box which can only slide a short way along the w axis, with a weak motor
+    // pushing it towards one end
+    let slider_body = physics::RigidBody {
+        position: na::vec4(2.0, -1.45, 3.0, 0.0),
+        ..Default::default()
+    };
+    let slider = spawn_box(world, slider_body, na::vec4(1.0, 1.0, 1.0, 1.0));
+    constraints.add_joint(
+        floor,
+        slider,
+        joint::SliderJoint::new(
+            &floor_body,
+            &slider_body,
+            slider_body.position,
+            na::vec4(0.0, 0.0, 0.0, 1.0),
+        )
+        .with_limits(-2.0, 2.0)
+        .with_motor(joint::Motor::Velocity {
+            speed: 1.0,
+            max_impulse: 0.02,
+        }),
+    );
+
+    // Two boxes welded into one, which can be broken apart
+    let a_body = physics::RigidBody {
+        position: na::vec4(-2.0, -1.5, -3.0, 0.0),
+        ..Default::default()
+    };
+    let b_body = physics::RigidBody {
+        position: na::vec4(-1.5, -0.5, -3.0, 0.0),
+        ..Default::default()
+    };
+    let a = spawn_box(world, a_body, na::vec4(1.0, 1.0, 1.0, 1.0));
+    let b = spawn_box(world, b_body, na::vec4(1.0, 1.0, 1.0, 1.0));
+    constraints.add_joint(
+        a,
+        b,
+        joint::Breakable::new(
+            joint::WeldJoint::new(&a_body, &b_body, na::vec4(-1.75, -1.0, -3.0, 0.0)),
+            1.0,
+            0.5,
+        ),
+    );
+}
+
+// A few things affected by gravity in different ways
+fn build_gravity(world: &mut hecs::World) {
+    // Somewhere to land when gravity points along w
+    let ground_body = physics::RigidBody {
+        position: na::vec4(0.0, -2.0, 0.0, -3.1),
+        ..Default::default()
+    }
+    .with_body_type(physics::BodyType::Static);
+    spawn_box(world, ground_body, na::vec4(10.0, 10.0, 10.0, 0.2));
+
+    // A "planet", with a moon in orbit around it. The moon ignores the world's
+    // gravity, so the planet is the only thing pulling on it.
+    let planet_body = physics::RigidBody {
+        position: na::vec4(0.0, 4.0, 4.0, 0.0),
+        ..Default::default()
+    }
+    .with_body_type(physics::BodyType::Static);
+    let planet = spawn_box(world, planet_body, na::vec4(1.0, 1.0, 1.0, 1.0));
+    let attractor = gravity::Attractor {
+        strength: 20.0,
+        radius: 0.5,
+    };
+    world.insert_one(planet, attractor).unwrap();
+
+    let orbit_radius = 2.5;
+    let moon = spawn_box(
+        world,
+        physics::RigidBody {
+            position: planet_body.position + na::vec4(orbit_radius, 0.0, 0.0, 0.0),
+            velocity: na::vec4(0.0, 0.0, attractor.strength.sqrt() / orbit_radius, 0.0),
+            ..Default::default()
+        },
+        na::vec4(0.4, 0.4, 0.4, 0.4),
+    );
+    world
+        .insert_one(
+            moon,
+            gravity::Override {
+                vector: na::Vector4::zeros(),
+            },
+        )
+        .unwrap();
+
+    // A zone with upwards gravity, and a box bobbing around in it
+    let zone_mesh = mesh::Mesh4::cube().transformed(&na::Affine4::from_pos(
+        na::Vector4::zeros(),
+        na::Matrix4::identity(),
+        na::vec4(1.5, 2.0, 1.5, 1.5),
+    ));
+    world.spawn((
+        physics::RigidBody {
+            position: na::vec4(5.0, -1.0, -4.5, 0.0),
+            ..Default::default()
+        }
+        .with_body_type(physics::BodyType::Static),
+        collision::Collider::from_mesh4(&zone_mesh),
+        collision::Sensor::default(),
+        gravity::Zone {
+            vector: na::vec4(0.0, 4.0, 0.0, 0.0),
+        },
+        zone_mesh,
+        draw_state::DrawState {
+            contacts: 0,
+            hollow: true,
+        },
+    ));
+    spawn_box(
+        world,
+        physics::RigidBody {
+            position: na::vec4(5.0, -1.5, -4.5, 0.0),
+            ..Default::default()
+        },
+        na::vec4(0.5, 0.5, 0.5, 0.5),
+    );
+}
+
+// A pool with things floating in it, and a fan blowing a box around
+fn build_forces(world: &mut hecs::World) {
+    let fluid = forces::Fluid {
+        normal: na::vec4(0.0, 1.0, 0.0, 0.0),
+        level: -0.8,
+        density: 2.0,
+        drag: 2.0,
+    };
+    let pool_mesh = mesh::Mesh4::cube().transformed(&na::Affine4::from_pos(
+        na::Vector4::zeros(),
+        na::Matrix4::identity(),
+        na::vec4(2.2, 2.0, 2.2, 2.2),
+    ));
+    world.spawn((
+        physics::RigidBody {
+            position: na::vec4(-3.8, -1.0, 4.0, 0.0),
+            ..Default::default()
+        }
+        .with_body_type(physics::BodyType::Static),
+        collision::Collider::from_mesh4(&pool_mesh),
+        collision::Sensor::default(),
+        fluid,
+        pool_mesh,
+        draw_state::DrawState {
+            contacts: 0,
+            hollow: true,
+        },
+    ));
+    // Two boxes half as dense as the fluid, which float half out of it, and one
+    // which is too dense to float
+    for (position, density) in [
+        (na::vec4(-4.4, -0.5, 3.5, 0.0), 1.0),
+        (na::vec4(-3.2, 0.0, 4.4, 0.0), 1.0),
+        (na::vec4(-3.8, -1.5, 4.2, 0.0), 4.0),
+    ] {
+        let size: f32 = 0.6;
+        spawn_box(
+            world,
+            physics::RigidBody {
+                position,
+                ..Default::default()
+            }
+            .with_mass(density * size.powi(4)),
+            na::Vector4::repeat(size),
+        );
+    }
+
+    let fan_mesh = mesh::Mesh4::cube().transformed(&na::Affine4::from_pos(
+        na::Vector4::zeros(),
+        na::Matrix4::identity(),
+        na::vec4(2.0, 2.0, 2.0, 2.0),
+    ));
+    world.spawn((
+        physics::RigidBody {
+            position: na::vec4(-4.0, -1.0, -4.5, 0.0),
+            ..Default::default()
+        }
+        .with_body_type(physics::BodyType::Static),
+        collision::Collider::from_mesh4(&fan_mesh),
+        collision::Sensor::default(),
+        forces::Wind {
+            velocity: na::vec4(0.0, 10.0, 0.0, 0.0),
+        },
+        fan_mesh,
+        draw_state::DrawState {
+            contacts: 0,
+            hollow: true,
+        },
+    ));
+    let blown = spawn_box(
+        world,
+        physics::RigidBody {
+            position: na::vec4(-4.0, -1.5, -4.5, 0.0),
+            ..Default::default()
+        },
+        na::vec4(0.5, 0.5, 0.5, 0.5),
+    );
+    world
+        .insert_one(
+            blown,
+            forces::Drag {
+                linear: 0.3,
+                angular: 0.1,
+            },
+        )
+        .unwrap();
+}
+
+// A row of the regular polytopes, dropped onto the floor
+fn build_polytopes(world: &mut hecs::World) {
+    for (x, mesh) in [
+        (-3.0, mesh::Mesh4::five_cell()),
+        (-1.5, mesh::Mesh4::sixteen_cell()),
+        (0.0, mesh::Mesh4::twenty_four_cell()),
+        (1.5, mesh::Mesh4::six_hundred_cell()),
+        (3.0, mesh::Mesh4::one_twenty_cell()),
+    ] {
+        spawn_mesh(
+            world,
+            physics::RigidBody {
+                position: na::vec4(x, 0.0, -1.5, 0.0),
+                ..Default::default()
+            },
+            mesh.transformed(&na::Affine4::from_pos(
+                na::Vector4::zeros(),
+                na::Matrix4::identity(),
+                na::Vector4::repeat(0.6),
+            )),
+        );
+    }
+}
+
+// A row of round shapes, drawn with fewer tetrahedra from further away
+fn build_curved(world: &mut hecs::World) {
+    let mut spawn_curved = |position, mesh: mesh::Mesh4| {
+        let lod = mesh_renderer::Lod::new(&mesh, &[6.0, 12.0]);
+        let entity = spawn_mesh(
+            world,
+            physics::RigidBody {
+                position,
+                ..Default::default()
+            },
+            mesh,
+        );
+        world.insert_one(entity, lod).unwrap();
+    };
+    let resolution = 12;
+    for (x, mesh) in [
+        (-3.75, mesh::Mesh4::glome(resolution)),
+        (-2.25, mesh::Mesh4::spherinder(resolution)),
+        (-0.75, mesh::Mesh4::cubinder(resolution)),
+        (0.75, mesh::Mesh4::duocylinder(resolution)),
+        (2.25, mesh::Mesh4::tiger(resolution)),
+        (3.75, mesh::Mesh4::cone(resolution)),
+    ] {
+        spawn_curved(
+            na::vec4(x, 0.0, 1.5, 0.0),
+            mesh.transformed(&na::Affine4::from_pos(
+                na::Vector4::zeros(),
+                na::Matrix4::identity(),
+                na::Vector4::repeat(0.6),
+            )),
+        );
+    }
+    spawn_curved(
+        na::vec4(0.0, 1.0, 1.5, 0.0),
+        mesh::Mesh4::ditorus(resolution),
+    );
+}
+
+// Shapes lifted from lower dimensions - an extruded icosahedron, a duoprism and
+// an icosahedron spun around a plane into a ring
+fn build_lifted(world: &mut hecs::World) {
+    let mut ring = lift::Mesh3::icosahedron();
+    for vertex in ring.vertices.iter_mut() {
+        vertex[0] += 0.7;
+    }
+    for (x, mesh) in [
+        (-2.4, mesh::Mesh4::extrude(&lift::Mesh3::icosahedron(), 1.0)),
+        (
+            -1.2,
+            mesh::Mesh4::duoprism(
+                &lift::regular_polygon(3, 0.5),
+                &lift::regular_polygon(5, 0.5),
+            ),
+        ),
+        (1.5, mesh::Mesh4::revolve(&ring, 16)),
+    ] {
+        spawn_mesh(
+            world,
+            physics::RigidBody {
+                position: na::vec4(x, 0.0, 0.0, 0.0),
+                ..Default::default()
+            },
+            mesh.transformed(&na::Affine4::from_pos(
+                na::Vector4::zeros(),
+                na::Matrix4::identity(),
+                na::Vector4::repeat(0.5),
+            )),
+        );
+    }
+}
+
+// Shapes built from distance functions - a tesseract hollowed out by a glome
+// with a bar blended through the middle, and a duocylinder cut in half with a
+// glome stuck on
+fn build_sdf(world: &mut hecs::World) {
+    let frame = sdf::Sdf::cuboid(na::Vector4::repeat(0.35))
+        .subtract(sdf::Sdf::glome(0.45))
+        .blend(sdf::Sdf::spherinder(0.12, 0.5), 0.1);
+    let half_duocylinder = sdf::Sdf::duocylinder([0.35, 0.2])
+        .intersect(sdf::Sdf::half_space(na::vec4(0.0, 1.0, 0.0, 1.0), 0.15))
+        .union(sdf::Sdf::glome(0.15).translated(na::vec4(0.0, 0.3, 0.0, 0.3)));
+    for (x, shape) in [(-0.75, frame), (0.75, half_duocylinder)] {
+        spawn_mesh(
+            world,
+            physics::RigidBody {
+                position: na::vec4(x, 0.0, 3.0, 0.0),
+                ..Default::default()
+            },
+            mesh::Mesh4::from_sdf(
+                &shape,
+                na::Vector4::repeat(-0.6),
+                na::Vector4::repeat(0.6),
+                8,
+            ),
+        );
+    }
+}
+
+// A tesseract and a glome made of wood, with the grain running through them in
+// 4D, so it shifts as they're sliced at different w
+fn build_textured(world: &mut hecs::World) {
+    let grain = |p: &na::Vector4| na::Vector3::new(p[0], p[1], p[2] + p[3]) * 0.5;
+    for (x, mesh) in [
+        (
+            -2.25,
+            mesh::Mesh4::cube().transformed(&na::Affine4::from_pos(
+                na::Vector4::zeros(),
+                na::Matrix4::identity(),
+                na::Vector4::repeat(0.8),
+            )),
+        ),
+        (
+            2.25,
+            mesh::Mesh4::glome(12).transformed(&na::Affine4::from_pos(
+                na::Vector4::zeros(),
+                na::Matrix4::identity(),
+                na::Vector4::repeat(0.8),
+            )),
+        ),
+    ] {
+        spawn_mesh(
+            world,
+            physics::RigidBody {
+                position: na::vec4(x, 0.0, 3.0, 0.0),
+                ..Default::default()
+            },
+            mesh::Mesh4 {
+                colors: vec![na::vec4(0.8, 0.55, 0.3, 1.0); mesh.vertices.len()],
+                ..mesh.textured(grain)
+            },
+        );
+    }
+}
+
+// A tesseract with a glome cut out of it, the part of it inside the glome, and
+// the two together
+fn build_csg(world: &mut hecs::World) {
+    let cube = mesh::Mesh4::cube();
+    let glome = mesh::Mesh4::glome(6).transformed(&na::Affine4::from_pos(
+        na::Vector4::zeros(),
+        na::Matrix4::identity(),
+        na::Vector4::repeat(1.2),
+    ));
+    for (x, mesh) in [
+        (1.0, cube.difference(&glome)),
+        (2.25, cube.intersection(&glome)),
+        (3.5, cube.union(&glome)),
+    ] {
+        spawn_mesh(
+            world,
+            physics::RigidBody {
+                position: na::vec4(x, 0.0, -3.0, 0.0),
+                ..Default::default()
+            },
+            mesh.transformed(&na::Affine4::from_pos(
+                na::Vector4::zeros(),
+                na::Matrix4::identity(),
+                na::Vector4::repeat(0.6),
+            )),
+        );
+    }
+}
+
+// A double pyramid over a tetrahedron, given as bare cells with no thought for
+// which way they're wound
+fn build_bipyramid(world: &mut hecs::World) {
+    let builder = mesh_builder::Mesh4Builder {
+        vertices: vec![
+            na::vec4(0.4, 0.4, 0.4, 0.0),
+            na::vec4(0.4, -0.4, -0.4, 0.0),
+            na::vec4(-0.4, 0.4, -0.4, 0.0),
+            na::vec4(-0.4, -0.4, 0.4, 0.0),
+            na::vec4(0.0, 0.0, 0.0, 0.5),
+            na::vec4(0.0, 0.0, 0.0, -0.5),
+        ],
+        cells: [[0, 1, 2], [0, 1, 3], [0, 2, 3], [1, 2, 3]]
+            .iter()
+            .flat_map(|&[a, b, c]| [[a, b, c, 4], [a, b, c, 5]])
+            .collect(),
+        ..Default::default()
+    };
+    match builder.build() {
+        Ok(mesh) => {
+            spawn_mesh(
+                world,
+                physics::RigidBody {
+                    position: na::vec4(3.5, 0.0, 3.0, 0.0),
+                    ..Default::default()
+                },
+                mesh,
+            );
+        }
+        Err(problems) => {
+            for problem in problems {
+                log::error!("bipyramid: {}", problem);
+            }
+        }
+    }
+}
+
+// A long, flat box floating in zero gravity, spinning almost exactly in a plane
+// whose moment of inertia is between the others. Rotation in that plane is
+// unstable, so the box periodically flips over.
+fn build_spinner(world: &mut hecs::World) {
+    let size = na::vec4(1.6, 0.8, 0.4, 0.2);
+    spawn_box(
+        world,
+        physics::RigidBody {
+            position: na::vec4(3.0, 2.0, -3.0, 0.0),
+            angular_velocity: ga::Bivector4::from_vector(na::Vector6::new(
+                0.02, 3.0, 0.01, 0.03, 0.01, 0.02,
+            )),
+            gravity: 0.0,
+            ..Default::default()
+        }
+        .with_inertia(physics::box_inertia_tensor(1.0, size)),
+        size,
+    );
+}
+
+fn build_world() -> (hecs::Entity, hecs::World, constraints::Constraints) {
     let mut world = hecs::World::new();
+    let mut constraints = constraints::Constraints::new();
 
     let player_entity = world.spawn((
         actor::Actor {
             move_thrust: 3000.0,
             look_torque: 500.0,
             grab_state: actor::GrabState::Not,
+            grab_spring: joint::GrabSpring::default(),
+            throw_impulse: 10.0,
         },
         mesh_renderer::Camera {
             fovy: 1.0,
@@ -152,8 +926,8 @@ fn build_world() -> (hecs::Entity, hecs::World) {
         },
         physics::RigidBody {
             position: na::vec4(0.0, 0.0, -4.0, 0.0),
-            linear_damping: 0.9,
-            angular_damping: 0.9,
+            linear_damping: 12.5,
+            angular_damping: 12.5,
             gravity: 0.0,
             ..Default::default()
         }
@@ -165,15 +939,48 @@ fn build_world() -> (hecs::Entity, hecs::World) {
             na::Matrix4::identity(),
             na::vec4(10.0, 10.0, 10.0, 10.0),
         ));
+        let floor_body = physics::RigidBody {
+            position: na::vec4(0.0, -7.0, 0.0, 0.0),
+            ..Default::default()
+        }
+        .with_body_type(physics::BodyType::Static);
+        let floor = world.spawn((
+            floor_body,
+            collision::Collider::from_mesh4(&floor_mesh),
+            floor_mesh,
+            draw_state::DrawState {
+                contacts: 0,
+                hollow: false,
+            },
+        ));
+        build_joints(&mut world, &mut constraints, (floor, floor_body));
+    }
+    build_gravity(&mut world);
+    build_forces(&mut world);
+    build_spinner(&mut world);
+    build_polytopes(&mut world);
+    build_curved(&mut world);
+    build_lifted(&mut world);
+    build_sdf(&mut world);
+    build_bipyramid(&mut world);
+    build_csg(&mut world);
+    build_textured(&mut world);
+    {
+        // A slowly turning platform, to show off kinematic bodies
+        let platform_mesh = mesh::Mesh4::cube().transformed(&na::Affine4::from_pos(
+            na::Vector4::zeros(),
+            na::Matrix4::identity(),
+            na::vec4(3.0, 0.2, 3.0, 3.0),
+        ));
         world.spawn((
             physics::RigidBody {
-                position: na::vec4(0.0, -7.0, 0.0, 0.0),
-                gravity: 0.0,
+                position: na::vec4(4.0, -1.5, 0.0, 0.0),
+                angular_velocity: na::vec4(1.0, 0.0, 0.0, 0.0).wedge(na::vec4(0.0, 0.0, 0.2, 0.0)),
                 ..Default::default()
             }
-            .with_mass(f32::INFINITY),
-            collision::Collider::from_mesh4(&floor_mesh),
-            floor_mesh,
+            .with_body_type(physics::BodyType::Kinematic),
+            collision::Collider::from_mesh4(&platform_mesh),
+            platform_mesh,
             draw_state::DrawState {
                 contacts: 0,
                 hollow: false,
@@ -207,18 +1014,41 @@ fn build_world() -> (hecs::Entity, hecs::World) {
             hollow: false,
         },
     ));
-    (player_entity, world)
+    (player_entity, world, constraints)
+}
+
+// Drop each mesh file named on the command line into the world, in a row in
+// front of the player
+#[cfg(not(target_arch = "wasm32"))]
+fn load_meshes(world: &mut hecs::World, paths: impl Iterator<Item = String>) {
+    for (i, path) in paths.enumerate() {
+        match mesh_file::load(&path) {
+            Ok(mesh) => {
+                spawn_mesh(
+                    world,
+                    physics::RigidBody {
+                        position: na::vec4(i as f32 * 1.5, 2.0, -2.5, 0.0),
+                        ..Default::default()
+                    },
+                    mesh,
+                );
+            }
+            Err(error) => log::error!("couldn't load {}: {}", path, error),
+        }
+    }
 }
 
 fn main() {
     let event_loop = EventLoop::new();
-    let (player_entity, world) = build_world();
+    let (player_entity, world, constraints) = build_world();
 
     #[cfg(not(target_arch = "wasm32"))]
     {
         env_logger::init();
+        let mut world = world;
+        load_meshes(&mut world, std::env::args().skip(1));
         let window = WindowBuilder::new().build(&event_loop).unwrap();
-        pollster::block_on(run(event_loop, window, world, player_entity));
+        pollster::block_on(run(event_loop, window, world, constraints, player_entity));
     }
 
     #[cfg(target_arch = "wasm32")]
@@ -239,6 +1069,12 @@ fn main() {
             .build(&event_loop)
             .unwrap();
 
-        wasm_bindgen_futures::spawn_local(run(event_loop, window, world, player_entity));
+        wasm_bindgen_futures::spawn_local(run(
+            event_loop,
+            window,
+            world,
+            constraints,
+            player_entity,
+        ));
     }
 }
@@ -1,12 +1,15 @@
 use crate::na;
 use crate::na::vec4;
 
+use crate::ga::Wedge;
+
 #[repr(C)]
 #[derive(Copy, Clone, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct Tetrahedron {
     positions: na::Matrix4,
     normals: na::Matrix4,
     colors: na::Matrix4,
+    materials: na::Matrix4,
 }
 
 #[repr(C)]
@@ -18,26 +21,97 @@ struct Mix {
     p2: u32, // padding
 }
 
+// The colors cells are painted with, in turn
+pub fn palette(i: usize) -> na::Vector4 {
+    [
+        vec4(1.0, 0.8, 0.1, 1.0),
+        vec4(0.0, 0.2, 0.3, 1.0),
+        vec4(0.5, 0.0, 0.1, 1.0),
+        vec4(0.3, 0.0, 0.4, 1.0),
+    ][i % 4]
+}
+
+// A normal to the hyperplane containing the tetrahedron, from the trivector of
+// its edges. Its length is proportional to the tetrahedron's volume, and which
+// way it points depends on the order of the vertices.
+pub fn tetrahedron_normal(v: &[na::Vector4; 4]) -> na::Vector4 {
+    (v[1] - v[0])
+        .wedge((v[2] - v[0]).wedge(v[3] - v[0]))
+        .normal()
+}
+
+// Numbers the vertices by where they are, so that copies of the same vertex
+// (within rounding) get the same number
+pub fn weld(vertices: &[na::Vector4]) -> Vec<usize> {
+    const TOLERANCE: f32 = 1e-5;
+    let mut ids = std::collections::HashMap::new();
+    vertices
+        .iter()
+        .map(|x| {
+            let next = ids.len();
+            *ids.entry([0, 1, 2, 3].map(|i| (x[i] / TOLERANCE).round() as i64))
+                .or_insert(next)
+        })
+        .collect()
+}
+
 #[derive(Debug, Clone)]
 pub struct Mesh4 {
     pub vertices: std::vec::Vec<na::Vector4>,
-    normals: std::vec::Vec<na::Vector4>,
-    colors: std::vec::Vec<na::Vector4>,
-    indices: std::vec::Vec<u32>,
+    pub normals: std::vec::Vec<na::Vector4>,
+    pub colors: std::vec::Vec<na::Vector4>,
+    // For sampling the material texture - xyz are 3D texture coordinates, and w
+    // is how much the texture shows through the color
+    pub materials: std::vec::Vec<na::Vector4>,
+    pub indices: std::vec::Vec<u32>,
     pub num_tetrahedra: usize,
 }
 
 impl Mesh4 {
+    // Each group of 4 indices is a tetrahedron of the boundary. There's no
+    // texture until one is given with textured.
+    pub fn new(
+        vertices: Vec<na::Vector4>,
+        normals: Vec<na::Vector4>,
+        colors: Vec<na::Vector4>,
+        indices: Vec<u32>,
+    ) -> Self {
+        let num_tetrahedra = indices.len() / 4;
+        Self {
+            materials: vec![na::Vector4::zeros(); vertices.len()],
+            vertices,
+            normals,
+            colors,
+            indices,
+            num_tetrahedra,
+        }
+    }
+
     pub fn transformed(self, transform: &na::Affine4) -> Self {
         Self {
             vertices: self.vertices.iter().map(|x| *transform * *x).collect(),
             normals: self.normals.iter().map(|x| transform.linear * *x).collect(),
             colors: self.colors,
+            materials: self.materials,
             indices: self.indices,
             num_tetrahedra: self.num_tetrahedra,
         }
     }
 
+    // Gives each vertex texture coordinates from its position, with the texture
+    // showing fully. The coordinates stay with the vertices when the mesh is
+    // transformed, so the pattern moves with it.
+    pub fn textured(self, texcoords: impl Fn(&na::Vector4) -> na::Vector3) -> Self {
+        Self {
+            materials: self
+                .vertices
+                .iter()
+                .map(|x| texcoords(x).push(1.0))
+                .collect(),
+            ..self
+        }
+    }
+
     pub fn cube() -> Self {
         #[rustfmt::skip]
         let vertices: Vec<na::Vector4> = vec![
@@ -72,15 +146,7 @@ impl Mesh4 {
         .flat_map(|x| [*x].repeat(8))
         .collect();
 
-        let colors: Vec<na::Vector4> = [
-            vec4(1.0, 0.8, 0.1, 1.0),
-            vec4(0.0, 0.2, 0.3, 1.0),
-            vec4(0.5, 0.0, 0.1, 1.0),
-            vec4(0.3, 0.0, 0.4, 1.0),
-        ]
-        .iter()
-        .flat_map(|x| [*x].repeat(16))
-        .collect();
+        let colors: Vec<na::Vector4> = (0..4).flat_map(|i| [palette(i)].repeat(16)).collect();
 
         let mut indices: Vec<u32> = Vec::new();
         for i in 0..(vertices.len() / 8) as u32 {
@@ -95,15 +161,28 @@ impl Mesh4 {
             indices.extend_from_slice(&[ix + 3, ix + 2, ix + 1, ix + 6]);
         }
 
-        let num_tetrahedra = indices.len() / 4;
+        Self::new(vertices, normals, colors, indices)
+    }
 
-        Self {
-            vertices,
-            normals,
-            colors,
-            indices,
-            num_tetrahedra,
-        }
+    // Each tetrahedron of the boundary, along with its outward unit normal
+    pub fn cells(&self) -> impl Iterator<Item = ([na::Vector4; 4], na::Vector4)> + '_ {
+        self.indices.chunks(4).map(|ix| {
+            let v = [0, 1, 2, 3].map(|i| self.vertices[ix[i] as usize]);
+            let normal = tetrahedron_normal(&v)
+                .try_normalize(0.0)
+                .unwrap_or_else(na::Vector4::zeros);
+            // The winding of the tetrahedra isn't consistent, so use the vertex
+            // normals to tell which way is out
+            let outward: na::Vector4 = ix.iter().map(|&i| self.normals[i as usize]).sum();
+            (
+                v,
+                if normal.dot(&outward) < 0.0 {
+                    -normal
+                } else {
+                    normal
+                },
+            )
+        })
     }
 
     pub fn get_buffer_data(&self) -> Vec<Tetrahedron> {
@@ -128,6 +207,12 @@ impl Mesh4 {
                     self.colors[self.indices[i + 2] as usize],
                     self.colors[self.indices[i + 3] as usize],
                 ]),
+                materials: na::Matrix4::from_columns(&[
+                    self.materials[self.indices[i] as usize],
+                    self.materials[self.indices[i + 1] as usize],
+                    self.materials[self.indices[i + 2] as usize],
+                    self.materials[self.indices[i + 3] as usize],
+                ]),
             })
             .flat_map(|x| itertools::repeat_n(x, 7))
             .collect()
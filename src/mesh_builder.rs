@@ -0,0 +1,493 @@
+use crate::mesh;
+use crate::na;
+
+use std::collections::{HashMap, VecDeque};
+
+// Builds a Mesh4 from just the vertices and tetrahedral cells of its boundary,
+// working out the normals from the cells themselves. The boundary has to be
+// closed, with every triangular face shared by exactly two cells - build says
+// which cells are at fault when it isn't.
+#[derive(Debug, Clone)]
+pub struct Mesh4Builder {
+    pub vertices: Vec<na::Vector4>,
+    pub cells: Vec<[u32; 4]>,
+    // One per vertex. Without them, each vertex is colored by the axis its
+    // normal is closest to.
+    pub colors: Option<Vec<na::Vector4>>,
+    // Share vertices between cells and average their normals, rather than giving
+    // each cell its own flat shaded copy
+    pub smooth: bool,
+    // Rewind cells to agree with their neighbours and face outward, rather than
+    // reporting the ones which don't
+    pub reorient: bool,
+}
+
+impl Default for Mesh4Builder {
+    fn default() -> Self {
+        Self {
+            vertices: Vec::new(),
+            cells: Vec::new(),
+            colors: None,
+            smooth: false,
+            reorient: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+    // A cell with a vertex out of range or repeated, or with no volume
+    Degenerate { cell: usize },
+    // A face with only one cell on it, so there's a hole in the boundary
+    Open { cell: usize, face: [u32; 3] },
+    // A face with more than two cells on it
+    NonManifold { cells: Vec<usize>, face: [u32; 3] },
+    // Two cells wound the same way across a face they share. When reorienting,
+    // this means the boundary can't be wound consistently at all.
+    Inconsistent { cells: [usize; 2], face: [u32; 3] },
+}
+
+impl std::fmt::Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Problem::Degenerate { cell } => write!(f, "cell {} is degenerate", cell),
+            Problem::Open { cell, face } => {
+                write!(f, "face {:?} of cell {} is on no other cell", face, cell)
+            }
+            Problem::NonManifold { cells, face } => {
+                write!(f, "face {:?} is shared by cells {:?}", face, cells)
+            }
+            Problem::Inconsistent { cells, face } => write!(
+                f,
+                "cells {} and {} are wound inconsistently across face {:?}",
+                cells[0], cells[1], face
+            ),
+        }
+    }
+}
+
+// The faces of a cell, each sorted, along with which way the cell winds it
+// compared with the sorted order. Two cells wound consistently wind a face they
+// share opposite ways.
+fn faces(cell: &[u32; 4]) -> [([u32; 3], bool); 4] {
+    let [a, b, c, d] = *cell;
+    [
+        ([b, c, d], true),
+        ([a, c, d], false),
+        ([a, b, d], true),
+        ([a, b, c], false),
+    ]
+    .map(|(mut face, mut sign)| {
+        // Sorting three things by swaps
+        for (i, j) in [(0, 1), (1, 2), (0, 1)] {
+            if face[i] > face[j] {
+                face.swap(i, j);
+                sign = !sign;
+            }
+        }
+        (face, sign)
+    })
+}
+
+impl Mesh4Builder {
+    pub fn build(self) -> Result<mesh::Mesh4, Vec<Problem>> {
+        let mut cells = self.cells.clone();
+        let mut problems = Vec::new();
+
+        let corners = |cell: &[u32; 4]| cell.map(|i| self.vertices[i as usize]);
+        let degenerate: Vec<bool> = cells
+            .iter()
+            .map(|cell| {
+                cell.iter().any(|&i| i as usize >= self.vertices.len())
+                    || (0..4).any(|i| cell[i + 1..].contains(&cell[i]))
+                    || mesh::tetrahedron_normal(&corners(cell)).norm() < 1e-12
+            })
+            .collect();
+        for (cell, _) in degenerate.iter().enumerate().filter(|(_, &x)| x) {
+            problems.push(Problem::Degenerate { cell });
+        }
+
+        let mut face_cells: HashMap<[u32; 3], Vec<(usize, bool)>> = HashMap::new();
+        for (i, cell) in cells.iter().enumerate() {
+            if degenerate[i] {
+                continue;
+            }
+            for (face, sign) in faces(cell) {
+                face_cells.entry(face).or_default().push((i, sign));
+            }
+        }
+        // Each cell's neighbours across its manifold faces, and whether they're
+        // wound consistently with it
+        let mut neighbours: Vec<Vec<(usize, bool, [u32; 3])>> = vec![Vec::new(); cells.len()];
+        for (&face, on) in face_cells.iter() {
+            match on[..] {
+                [(cell, _)] => problems.push(Problem::Open { cell, face }),
+                [(a, a_sign), (b, b_sign)] => {
+                    neighbours[a].push((b, a_sign != b_sign, face));
+                    neighbours[b].push((a, a_sign != b_sign, face));
+                }
+                _ => problems.push(Problem::NonManifold {
+                    cells: on.iter().map(|x| x.0).collect(),
+                    face,
+                }),
+            }
+        }
+
+        if self.reorient {
+            // Flood each connected piece, flipping cells to agree with the first
+            let mut flip: Vec<Option<bool>> = vec![None; cells.len()];
+            let mut pieces: Vec<Vec<usize>> = Vec::new();
+            for start in 0..cells.len() {
+                if degenerate[start] || flip[start].is_some() {
+                    continue;
+                }
+                flip[start] = Some(false);
+                let mut piece = vec![start];
+                let mut queue = VecDeque::from([start]);
+                while let Some(cell) = queue.pop_front() {
+                    for &(other, consistent, face) in neighbours[cell].iter() {
+                        let other_flip = flip[cell].unwrap() ^ !consistent;
+                        match flip[other] {
+                            None => {
+                                flip[other] = Some(other_flip);
+                                piece.push(other);
+                                queue.push_back(other);
+                            }
+                            Some(x) if x != other_flip && cell < other => {
+                                problems.push(Problem::Inconsistent {
+                                    cells: [cell, other],
+                                    face,
+                                })
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                pieces.push(piece);
+            }
+            for (i, cell) in cells.iter_mut().enumerate() {
+                if flip[i] == Some(true) {
+                    cell.swap(2, 3);
+                }
+            }
+
+            // Then make each piece face outward, which is away from its inside
+            // unless it's the wall of a hole in another piece
+            for (i, piece) in pieces.iter().enumerate() {
+                let content: f32 = piece
+                    .iter()
+                    .map(|&cell| {
+                        let v = corners(&cells[cell]);
+                        mesh::tetrahedron_normal(&v).dot(&v[0]) / 24.0
+                    })
+                    .sum();
+                let v = corners(&cells[piece[0]]);
+                let point = v.iter().sum::<na::Vector4>() / 4.0;
+                let crossings = pieces
+                    .iter()
+                    .enumerate()
+                    .filter(|(j, _)| *j != i)
+                    .flat_map(|(_, other)| other.iter())
+                    .filter(|&&cell| crosses(point, &corners(&cells[cell])))
+                    .count();
+                let hole = crossings % 2 == 1;
+                if (content < 0.0) != hole {
+                    for &cell in piece.iter() {
+                        cells[cell].swap(2, 3);
+                    }
+                }
+            }
+        } else {
+            for (cell, on) in neighbours.iter().enumerate() {
+                for &(other, consistent, face) in on.iter() {
+                    if !consistent && cell < other {
+                        problems.push(Problem::Inconsistent {
+                            cells: [cell, other],
+                            face,
+                        });
+                    }
+                }
+            }
+        }
+
+        if !problems.is_empty() {
+            return Err(problems);
+        }
+
+        let cell_normals: Vec<na::Vector4> = cells
+            .iter()
+            .map(|cell| mesh::tetrahedron_normal(&corners(cell)))
+            .collect();
+        // Along with which of the given vertices each output vertex came from
+        let (vertices, normals, indices, sources): (Vec<_>, Vec<_>, Vec<_>, Vec<u32>) =
+            if self.smooth {
+                // Vertices no cell uses are left out, as they'd have no normal
+                let mut new_index = vec![None; self.vertices.len()];
+                let mut sources = Vec::new();
+                let indices: Vec<u32> = cells
+                    .iter()
+                    .flatten()
+                    .map(|&i| {
+                        *new_index[i as usize].get_or_insert_with(|| {
+                            sources.push(i);
+                            sources.len() as u32 - 1
+                        })
+                    })
+                    .collect();
+                // Bigger cells have longer normals, so they count for more. Where
+                // they cancel out, as they might on a sheet folded back on itself,
+                // there's no way to tell which way the vertex faces.
+                let mut normals = vec![na::Vector4::zeros(); sources.len()];
+                for (cell, normal) in indices.chunks(4).zip(cell_normals.iter()) {
+                    for &i in cell {
+                        normals[i as usize] += normal;
+                    }
+                }
+                (
+                    sources.iter().map(|&i| self.vertices[i as usize]).collect(),
+                    normals
+                        .iter()
+                        .map(|x| x.try_normalize(0.0).unwrap_or_else(na::Vector4::zeros))
+                        .collect(),
+                    indices,
+                    sources,
+                )
+            } else {
+                let sources: Vec<u32> = cells.iter().flatten().copied().collect();
+                (
+                    sources.iter().map(|&i| self.vertices[i as usize]).collect(),
+                    cell_normals
+                        .iter()
+                        .flat_map(|x| [x.normalize(); 4])
+                        .collect(),
+                    (0..sources.len() as u32).collect(),
+                    sources,
+                )
+            };
+        let colors = match &self.colors {
+            Some(colors) => sources.iter().map(|&i| colors[i as usize]).collect(),
+            None => normals
+                .iter()
+                .map(|x: &na::Vector4| mesh::palette(x.iamax()))
+                .collect(),
+        };
+        Ok(mesh::Mesh4::new(vertices, normals, colors, indices))
+    }
+}
+
+// Whether a ray from the point along a fixed direction passes through the
+// tetrahedron. The direction is chosen not to line up with anything in
+// particular, so it's unlikely to hit an edge exactly.
+fn crosses(point: na::Vector4, tetrahedron: &[na::Vector4; 4]) -> bool {
+    let direction = na::vec4(0.5377, 0.1834, -0.2258, 0.7889);
+    let normal = mesh::tetrahedron_normal(tetrahedron);
+    let denominator = normal.dot(&direction);
+    if denominator.abs() < 1e-12 {
+        return false;
+    }
+    let t = normal.dot(&(tetrahedron[0] - point)) / denominator;
+    if t <= 0.0 {
+        return false;
+    }
+    // Barycentric coordinates of where it meets the tetrahedron's hyperplane
+    let edges = na::Matrix4x3::from_columns(&[
+        tetrahedron[1] - tetrahedron[0],
+        tetrahedron[2] - tetrahedron[0],
+        tetrahedron[3] - tetrahedron[0],
+    ]);
+    let offset = point + direction * t - tetrahedron[0];
+    match (edges.transpose() * edges).try_inverse() {
+        Some(inverse) => {
+            let b = inverse * edges.transpose() * offset;
+            b.iter().all(|&x| x >= 0.0) && b.sum() <= 1.0
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    // The cells of a mesh with the vertices in the same place shared between
+    // them, for checking the boundary of meshes built some other way
+    pub(crate) fn welded(mesh: &mesh::Mesh4) -> Mesh4Builder {
+        let welded = mesh::weld(&mesh.vertices);
+        let mut vertices = vec![na::Vector4::zeros(); welded.iter().max().map_or(0, |x| x + 1)];
+        for (vertex, &i) in mesh.vertices.iter().zip(welded.iter()) {
+            vertices[i] = *vertex;
+        }
+        let cells = mesh
+            .indices
+            .chunks(4)
+            .map(|cell| [0, 1, 2, 3].map(|i| welded[cell[i] as usize] as u32))
+            .collect();
+        Mesh4Builder {
+            vertices,
+            cells,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn content(mesh: &mesh::Mesh4) -> f32 {
+        mesh.cells()
+            .map(|(v, _)| mesh::tetrahedron_normal(&v).dot(&v[0]) / 24.0)
+            .sum()
+    }
+
+    // A tesseract with the given side, wound however Mesh4::cube happens to
+    // wind it
+    fn tesseract(side: f32) -> Mesh4Builder {
+        welded(&mesh::Mesh4::cube().transformed(&na::Affine4::from_pos(
+            na::Vector4::zeros(),
+            na::Matrix4::identity(),
+            na::Vector4::repeat(side),
+        )))
+    }
+
+    // The same, but wound consistently and facing outward
+    fn wound_tesseract(side: f32) -> Mesh4Builder {
+        let mut builder = tesseract(side);
+        builder.smooth = true;
+        let mesh = builder.clone().build().unwrap();
+        Mesh4Builder {
+            vertices: mesh.vertices,
+            cells: mesh
+                .indices
+                .chunks(4)
+                .map(|cell| [cell[0], cell[1], cell[2], cell[3]])
+                .collect(),
+            reorient: false,
+            ..builder
+        }
+    }
+
+    #[test]
+    fn closed() {
+        let mesh = tesseract(2.0).build().unwrap();
+        assert!((content(&mesh) - 16.0).abs() < 1e-4);
+        assert_eq!(mesh.num_tetrahedra, 48);
+
+        let mesh = wound_tesseract(2.0).build().unwrap();
+        assert!((content(&mesh) - 16.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn smooth_normals() {
+        let mut builder = wound_tesseract(1.0);
+        builder.smooth = true;
+        // A vertex no cell uses is dropped, rather than given a normal of NaNs
+        builder.vertices.push(na::vec4(5.0, 5.0, 5.0, 5.0));
+        let mesh = builder.build().unwrap();
+        assert_eq!(mesh.vertices.len(), 16);
+        // The cells around each corner aren't symmetric, so the normals are only
+        // roughly along the diagonals
+        for (vertex, normal) in mesh.vertices.iter().zip(mesh.normals.iter()) {
+            assert!((normal.norm() - 1.0).abs() < 1e-4);
+            assert!(normal.dot(&vertex.normalize()) > 0.5);
+        }
+    }
+
+    #[test]
+    fn degenerate() {
+        let mut builder = tesseract(1.0);
+        builder.cells.push([0, 0, 1, 2]);
+        builder.cells.push([0, 1, 2, 99]);
+        let problems = builder.build().unwrap_err();
+        assert_eq!(
+            problems,
+            vec![
+                Problem::Degenerate { cell: 48 },
+                Problem::Degenerate { cell: 49 }
+            ]
+        );
+    }
+
+    #[test]
+    fn open() {
+        let mut builder = tesseract(1.0);
+        let removed = builder.cells.pop().unwrap();
+        let problems = builder.build().unwrap_err();
+        // Each face of the missing cell is left with only one cell on it
+        assert_eq!(problems.len(), 4);
+        for problem in problems {
+            let Problem::Open { face, .. } = problem else {
+                panic!("expected an open face, got {}", problem);
+            };
+            assert!(face.iter().all(|i| removed.contains(i)));
+        }
+    }
+
+    #[test]
+    fn non_manifold() {
+        let mut builder = tesseract(1.0);
+        let mut extra = builder.cells[0];
+        extra.swap(2, 3);
+        builder.cells.push(extra);
+        let problems = builder.build().unwrap_err();
+        assert_eq!(problems.len(), 4);
+        for problem in problems {
+            let Problem::NonManifold { mut cells, .. } = problem else {
+                panic!("expected a non-manifold face, got {}", problem);
+            };
+            cells.sort_unstable();
+            assert_eq!(cells.len(), 3);
+            assert_eq!([cells[0], cells[2]], [0, 48]);
+        }
+    }
+
+    #[test]
+    fn inconsistent() {
+        let mut builder = wound_tesseract(1.0);
+        builder.cells[5].swap(0, 1);
+        let problems = builder.build().unwrap_err();
+        assert_eq!(problems.len(), 4);
+        for problem in problems {
+            let Problem::Inconsistent { cells, .. } = problem else {
+                panic!("expected inconsistent winding, got {}", problem);
+            };
+            assert!(cells.contains(&5));
+        }
+    }
+
+    #[test]
+    fn reorient() {
+        // Some cells flipped, and then all of them, so the inside is outside
+        for every in [3, 1] {
+            let mut builder = wound_tesseract(1.0);
+            builder.reorient = true;
+            for cell in builder.cells.iter_mut().step_by(every) {
+                cell.swap(0, 1);
+            }
+            let mesh = builder.build().unwrap();
+            assert!((content(&mesh) - 1.0).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn hole() {
+        // A small tesseract inside a big one is the wall of a hole, so it's
+        // turned to face inward whichever way it started
+        for flip_inner in [false, true] {
+            let outer = tesseract(2.0);
+            let mut inner = tesseract(1.0);
+            if flip_inner {
+                for cell in inner.cells.iter_mut() {
+                    cell.swap(2, 3);
+                }
+            }
+            let offset = outer.vertices.len() as u32;
+            let builder = Mesh4Builder {
+                vertices: [outer.vertices, inner.vertices].concat(),
+                cells: outer
+                    .cells
+                    .into_iter()
+                    .chain(inner.cells.into_iter().map(|cell| cell.map(|i| i + offset)))
+                    .collect(),
+                ..Default::default()
+            };
+            let mesh = builder.build().unwrap();
+            assert!((content(&mesh) - 15.0).abs() < 1e-4);
+        }
+    }
+}
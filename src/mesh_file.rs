@@ -0,0 +1,442 @@
+use crate::mesh;
+use crate::na;
+
+use std::io::{BufRead, Read, Write};
+
+// Meshes are stored in one of two forms, holding the same things - the vertices,
+// each with a position, normal, color and material, then the tetrahedra of the
+// boundary as indices into them.
+//
+// The text form is like OFF, with a cell per line instead of a face:
+//
+//   OFF4
+//   # Comments run to the end of a line, and blank lines are skipped
+//   <vertex count> <tetrahedron count>
+//   x y z w  nx ny nz nw  r g b a  mx my mz mw  (once per vertex)
+//   4 i j k l                                   (once per tetrahedron)
+//
+// The leading 4 on each cell is its number of vertices, as in OFF, and is always
+// 4. The binary form is little endian throughout:
+//
+//   b"MSH4", version (u32, currently 2)
+//   vertex count (u32), tetrahedron count (u32)
+//   16 f32s per vertex, in the same order as the text form
+//   4 u32s per tetrahedron
+//
+// Files ending in .off4 are read and written as text, and anything else as
+// binary. Version 1 had no materials, with only 12 numbers per vertex - those
+// files, and text files written the same way, still load, untextured.
+
+const MAGIC: &[u8; 4] = b"MSH4";
+const VERSION: u32 = 2;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(std::io::Error),
+    // What was wrong, and the line it was on for the text form
+    Parse {
+        line: Option<usize>,
+        message: String,
+    },
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::Io(error) => write!(f, "{}", error),
+            Error::Parse {
+                line: Some(line),
+                message,
+            } => write!(f, "line {}: {}", line, message),
+            Error::Parse {
+                line: None,
+                message,
+            } => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+fn parse_error(line: Option<usize>, message: impl Into<String>) -> Error {
+    Error::Parse {
+        line,
+        message: message.into(),
+    }
+}
+
+fn is_text(path: &std::path::Path) -> bool {
+    path.extension().is_some_and(|x| x == "off4")
+}
+
+pub fn load(path: impl AsRef<std::path::Path>) -> Result<mesh::Mesh4, Error> {
+    let path = path.as_ref();
+    let file = std::io::BufReader::new(std::fs::File::open(path)?);
+    if is_text(path) {
+        read_text(file)
+    } else {
+        read_binary(file)
+    }
+}
+
+pub fn save(mesh: &mesh::Mesh4, path: impl AsRef<std::path::Path>) -> Result<(), Error> {
+    let path = path.as_ref();
+    let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+    if is_text(path) {
+        write_text(mesh, &mut file)?;
+    } else {
+        write_binary(mesh, &mut file)?;
+    }
+    file.flush()?;
+    Ok(())
+}
+
+pub fn write_text(mesh: &mesh::Mesh4, out: &mut impl Write) -> Result<(), Error> {
+    writeln!(out, "OFF4")?;
+    writeln!(out, "{} {}", mesh.vertices.len(), mesh.num_tetrahedra)?;
+    for i in 0..mesh.vertices.len() {
+        let fields = [
+            mesh.vertices[i],
+            mesh.normals[i],
+            mesh.colors[i],
+            mesh.materials[i],
+        ];
+        let line: Vec<String> = fields
+            .iter()
+            .map(|v| {
+                v.iter()
+                    .map(|x| x.to_string())
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect();
+        writeln!(out, "{}", line.join("  "))?;
+    }
+    for tetrahedron in mesh.indices.chunks(4) {
+        writeln!(
+            out,
+            "4 {} {} {} {}",
+            tetrahedron[0], tetrahedron[1], tetrahedron[2], tetrahedron[3]
+        )?;
+    }
+    Ok(())
+}
+
+pub fn read_text(input: impl BufRead) -> Result<mesh::Mesh4, Error> {
+    // Non-empty lines with the comments taken off, and their line numbers
+    let mut lines = Vec::new();
+    for (number, line) in input.lines().enumerate() {
+        let line = line?;
+        let line = line.split('#').next().unwrap().trim().to_string();
+        if !line.is_empty() {
+            lines.push((number + 1, line));
+        }
+    }
+    let mut lines = lines.into_iter();
+    let mut next_line = |what: &str| {
+        lines
+            .next()
+            .ok_or_else(|| parse_error(None, format!("ran out of lines reading {}", what)))
+    };
+
+    let (number, header) = next_line("the header")?;
+    if header != "OFF4" {
+        return Err(parse_error(Some(number), "expected OFF4"));
+    }
+    let (number, counts) = next_line("the counts")?;
+    let counts: Vec<usize> = parse_fields(number, &counts)?;
+    let [vertex_count, tetrahedron_count] = counts[..] else {
+        return Err(parse_error(Some(number), "expected two counts"));
+    };
+
+    let mut vertices = Vec::new();
+    let mut normals = Vec::new();
+    let mut colors = Vec::new();
+    let mut materials = Vec::new();
+    for _ in 0..vertex_count {
+        let (number, line) = next_line("vertices")?;
+        let mut fields: Vec<f32> = parse_fields(number, &line)?;
+        match fields.len() {
+            12 => fields.extend([0.0; 4]),
+            16 => {}
+            _ => {
+                return Err(parse_error(
+                    Some(number),
+                    "expected 16 numbers for a vertex, or 12 without a material",
+                ))
+            }
+        }
+        vertices.push(na::Vector4::from_column_slice(&fields[0..4]));
+        normals.push(na::Vector4::from_column_slice(&fields[4..8]));
+        colors.push(na::Vector4::from_column_slice(&fields[8..12]));
+        materials.push(na::Vector4::from_column_slice(&fields[12..16]));
+    }
+
+    let mut indices = Vec::new();
+    for _ in 0..tetrahedron_count {
+        let (number, line) = next_line("tetrahedra")?;
+        let fields: Vec<u32> = parse_fields(number, &line)?;
+        if fields.len() != 5 || fields[0] != 4 {
+            return Err(parse_error(
+                Some(number),
+                "expected 4 followed by 4 vertex indices",
+            ));
+        }
+        indices.extend_from_slice(&fields[1..]);
+    }
+    if let Some((number, _)) = lines.next() {
+        return Err(parse_error(
+            Some(number),
+            "unexpected line after the tetrahedra",
+        ));
+    }
+    build(vertices, normals, colors, materials, indices)
+}
+
+fn parse_fields<T: std::str::FromStr>(number: usize, line: &str) -> Result<Vec<T>, Error> {
+    line.split_whitespace()
+        .map(|field| {
+            field
+                .parse()
+                .map_err(|_| parse_error(Some(number), format!("couldn't read {:?}", field)))
+        })
+        .collect()
+}
+
+pub fn write_binary(mesh: &mesh::Mesh4, out: &mut impl Write) -> Result<(), Error> {
+    out.write_all(MAGIC)?;
+    for x in [
+        VERSION,
+        mesh.vertices.len() as u32,
+        mesh.num_tetrahedra as u32,
+    ] {
+        out.write_all(&x.to_le_bytes())?;
+    }
+    for i in 0..mesh.vertices.len() {
+        for v in [
+            mesh.vertices[i],
+            mesh.normals[i],
+            mesh.colors[i],
+            mesh.materials[i],
+        ] {
+            for x in v.iter() {
+                out.write_all(&x.to_le_bytes())?;
+            }
+        }
+    }
+    for x in mesh.indices.iter() {
+        out.write_all(&x.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+pub fn read_binary(mut input: impl Read) -> Result<mesh::Mesh4, Error> {
+    let mut magic = [0; 4];
+    input.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(parse_error(None, "not a binary Mesh4 file"));
+    }
+    let mut read_u32 = || -> Result<u32, Error> {
+        let mut bytes = [0; 4];
+        input.read_exact(&mut bytes)?;
+        Ok(u32::from_le_bytes(bytes))
+    };
+    let version = read_u32()?;
+    if !(1..=VERSION).contains(&version) {
+        return Err(parse_error(None, format!("unknown version {}", version)));
+    }
+    let vertex_count = read_u32()? as usize;
+    let tetrahedron_count = read_u32()? as usize;
+
+    let mut vertices = Vec::new();
+    let mut normals = Vec::new();
+    let mut colors = Vec::new();
+    let mut materials = Vec::new();
+    let mut read_vector = || -> Result<na::Vector4, Error> {
+        let mut v = na::Vector4::zeros();
+        for x in v.iter_mut() {
+            *x = f32::from_bits(read_u32()?);
+        }
+        Ok(v)
+    };
+    for _ in 0..vertex_count {
+        vertices.push(read_vector()?);
+        normals.push(read_vector()?);
+        colors.push(read_vector()?);
+        materials.push(if version >= 2 {
+            read_vector()?
+        } else {
+            na::Vector4::zeros()
+        });
+    }
+    let mut indices = Vec::new();
+    for _ in 0..tetrahedron_count * 4 {
+        indices.push(read_u32()?);
+    }
+    build(vertices, normals, colors, materials, indices)
+}
+
+fn build(
+    vertices: Vec<na::Vector4>,
+    normals: Vec<na::Vector4>,
+    colors: Vec<na::Vector4>,
+    materials: Vec<na::Vector4>,
+    indices: Vec<u32>,
+) -> Result<mesh::Mesh4, Error> {
+    if let Some(index) = indices.iter().find(|&&i| i as usize >= vertices.len()) {
+        return Err(parse_error(
+            None,
+            format!(
+                "vertex index {} is out of range, with {} vertices",
+                index,
+                vertices.len()
+            ),
+        ));
+    }
+    Ok(mesh::Mesh4 {
+        materials,
+        ..mesh::Mesh4::new(vertices, normals, colors, indices)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_same(a: &mesh::Mesh4, b: &mesh::Mesh4) {
+        assert_eq!(a.vertices, b.vertices);
+        assert_eq!(a.normals, b.normals);
+        assert_eq!(a.colors, b.colors);
+        assert_eq!(a.materials, b.materials);
+        assert_eq!(a.indices, b.indices);
+        assert_eq!(a.num_tetrahedra, b.num_tetrahedra);
+    }
+
+    fn textured_cube() -> mesh::Mesh4 {
+        mesh::Mesh4::cube().textured(|x| x.xyz() * 0.3)
+    }
+
+    fn parse_error_line(result: Result<mesh::Mesh4, Error>) -> Option<usize> {
+        match result {
+            Err(Error::Parse { line, .. }) => line,
+            other => panic!("expected a parse error, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn text_round_trip() {
+        let mesh = textured_cube();
+        let mut text = Vec::new();
+        write_text(&mesh, &mut text).unwrap();
+        assert_same(&mesh, &read_text(&text[..]).unwrap());
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        let mesh = textured_cube();
+        let mut bytes = Vec::new();
+        write_binary(&mesh, &mut bytes).unwrap();
+        assert_same(&mesh, &read_binary(&bytes[..]).unwrap());
+    }
+
+    #[test]
+    fn text_with_comments() {
+        let text = "\
+            # A single tetrahedron\n\
+            OFF4\n\
+            \n\
+            4 1  # counts\n\
+            0 0 0 0  0 0 0 1  1 0 0 1\n\
+            1 0 0 0  0 0 0 1  0 1 0 1\n\
+            0 1 0 0  0 0 0 1  0 0 1 1\n\
+            0 0 1 0  0 0 0 1  1 1 1 1\n\
+            4 0 1 2 3\n";
+        let mesh = read_text(text.as_bytes()).unwrap();
+        assert_eq!(mesh.vertices.len(), 4);
+        assert_eq!(mesh.indices, vec![0, 1, 2, 3]);
+        assert_eq!(mesh.colors[3], na::vec4(1.0, 1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn version_1() {
+        let mesh = mesh::Mesh4::cube();
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        for x in [1, mesh.vertices.len() as u32, mesh.num_tetrahedra as u32] {
+            bytes.extend_from_slice(&x.to_le_bytes());
+        }
+        for i in 0..mesh.vertices.len() {
+            for v in [mesh.vertices[i], mesh.normals[i], mesh.colors[i]] {
+                for x in v.iter() {
+                    bytes.extend_from_slice(&x.to_le_bytes());
+                }
+            }
+        }
+        for x in mesh.indices.iter() {
+            bytes.extend_from_slice(&x.to_le_bytes());
+        }
+        let read = read_binary(&bytes[..]).unwrap();
+        assert_same(&mesh, &read);
+        assert!(read.materials.iter().all(|x| *x == na::Vector4::zeros()));
+    }
+
+    #[test]
+    fn text_without_materials() {
+        let text = "OFF4\n1 0\n0 0 0 0  0 0 0 1  1 1 1 1\n";
+        let mesh = read_text(text.as_bytes()).unwrap();
+        assert_eq!(mesh.materials, vec![na::Vector4::zeros()]);
+    }
+
+    #[test]
+    fn index_out_of_range() {
+        let text = "OFF4\n1 1\n0 0 0 0  0 0 0 1  1 1 1 1\n4 0 0 0 1\n";
+        assert_eq!(parse_error_line(read_text(text.as_bytes())), None);
+
+        let mut mesh = mesh::Mesh4::cube();
+        mesh.indices[0] = mesh.vertices.len() as u32;
+        let mut bytes = Vec::new();
+        write_binary(&mesh, &mut bytes).unwrap();
+        assert_eq!(parse_error_line(read_binary(&bytes[..])), None);
+    }
+
+    #[test]
+    fn wrong_count_line() {
+        let text = "OFF4\n1\n0 0 0 0  0 0 0 1  1 1 1 1\n";
+        assert_eq!(parse_error_line(read_text(text.as_bytes())), Some(2));
+
+        let text = "OFF4\n1 x\n";
+        assert_eq!(parse_error_line(read_text(text.as_bytes())), Some(2));
+    }
+
+    #[test]
+    fn wrong_vertex_line() {
+        let text = "OFF4\n1 0\n0 0 0 0  0 0 0 1\n";
+        assert_eq!(parse_error_line(read_text(text.as_bytes())), Some(3));
+    }
+
+    #[test]
+    fn truncated_binary() {
+        let mut bytes = Vec::new();
+        write_binary(&mesh::Mesh4::cube(), &mut bytes).unwrap();
+        for length in [2, 10, bytes.len() / 2, bytes.len() - 1] {
+            match read_binary(&bytes[..length]) {
+                Err(Error::Io(error)) => {
+                    assert_eq!(error.kind(), std::io::ErrorKind::UnexpectedEof)
+                }
+                other => panic!("expected an io error, got {:?}", other.map(|_| ())),
+            }
+        }
+    }
+
+    #[test]
+    fn not_a_mesh() {
+        assert!(read_text("OFF\n0 0\n".as_bytes()).is_err());
+        assert!(read_binary(&b"MSH3\x01\0\0\0"[..]).is_err());
+    }
+}
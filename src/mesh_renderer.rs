@@ -1,12 +1,16 @@
 use crate::draw_state;
+use crate::ga;
 use crate::mesh;
 use crate::na;
 use crate::physics;
+use crate::stepper;
+use crate::texture;
 use crate::wgputil;
 
 use wgpu::util::DeviceExt;
 
 use itertools::Itertools;
+use std::f32::consts::TAU;
 
 #[derive(Debug, Copy, Clone)]
 pub struct Camera {
@@ -30,10 +34,14 @@ fn view_from_orientation(orientation: na::Matrix4) -> na::Matrix4 {
 }
 
 impl CameraUniforms {
-    fn from_components(camera: &Camera, aspect: f32, body: &physics::RigidBody) -> Self {
+    fn from_components(
+        camera: &Camera,
+        aspect: f32,
+        (position, orientation): (na::Vector4, ga::Rotor4),
+    ) -> Self {
         CameraUniforms {
-            view: view_from_orientation(body.orientation.to_matrix()),
-            position: body.position,
+            view: view_from_orientation(orientation.to_matrix()),
+            position,
             projection: na::Matrix4::new_perspective(
                 aspect,
                 camera.fovy,
@@ -44,14 +52,89 @@ impl CameraUniforms {
     }
 }
 
+// Simpler versions of an entity's mesh, each drawn instead of it when the camera
+// is at least that far away. They're in order of distance.
+pub struct Lod {
+    pub levels: Vec<(f32, mesh::Mesh4)>,
+    // Which vertex of the full mesh each vertex of each level came from
+    sources: Vec<Vec<usize>>,
+}
+
+// How far simplifying may move the boundary, as a fraction of the mesh's size
+const LOD_TOLERANCE: f32 = 0.01;
+
+impl Lod {
+    // Each level has at most half as many tetrahedra as the one before, and
+    // fewer if halving it would change its shape too much. Once a level can't be
+    // simplified any further, there are no more.
+    pub fn new(mesh: &mesh::Mesh4, distances: &[f32]) -> Self {
+        let (min, max) = mesh.vertices.iter().fold(
+            (
+                na::Vector4::repeat(f32::INFINITY),
+                na::Vector4::repeat(-f32::INFINITY),
+            ),
+            |(min, max), x| (min.inf(x), max.sup(x)),
+        );
+        let max_distance = (max - min).norm() * LOD_TOLERANCE;
+
+        let mut lod = Self {
+            levels: Vec::new(),
+            sources: Vec::new(),
+        };
+        for &distance in distances {
+            let previous = lod.levels.last().map_or(mesh, |x| &x.1);
+            let (simplified, sources) =
+                previous.simplified(previous.num_tetrahedra / 2, max_distance);
+            if simplified.num_tetrahedra == previous.num_tetrahedra {
+                break;
+            }
+            let sources = match lod.sources.last() {
+                Some(previous) => sources.iter().map(|&i| previous[i]).collect(),
+                None => sources,
+            };
+            lod.levels.push((distance, simplified));
+            lod.sources.push(sources);
+        }
+        lod
+    }
+
+    // The levels are copies, so after the full mesh has been painted, its colors
+    // and materials have to be copied over to them again
+    pub fn update(&mut self, mesh: &mesh::Mesh4) {
+        for ((_, level), sources) in self.levels.iter_mut().zip(self.sources.iter()) {
+            for (i, &source) in sources.iter().enumerate() {
+                level.colors[i] = mesh.colors[source];
+                level.materials[i] = mesh.materials[source];
+            }
+        }
+    }
+
+    fn level(&self, distance: f32) -> usize {
+        self.levels.iter().filter(|x| x.0 <= distance).count()
+    }
+}
+
 pub struct MeshBuffers {
-    num_vertices: u32,
-    tetrahedra_buffer: wgpu::Buffer,
+    // The number of vertices to draw for each, with the full mesh first and then
+    // each level of detail
+    tetrahedra_buffers: Vec<(u32, wgpu::Buffer)>,
+    level: usize,
     transform_buffer: wgpu::Buffer,
     draw_state_buffer: wgpu::Buffer,
     bind_group: wgpu::BindGroup,
 }
 
+impl MeshBuffers {
+    // Free the GPU memory now, rather than whenever wgpu gets around to it
+    pub fn destroy(&self) {
+        for (_, buffer) in self.tetrahedra_buffers.iter() {
+            buffer.destroy();
+        }
+        self.transform_buffer.destroy();
+        self.draw_state_buffer.destroy();
+    }
+}
+
 #[repr(C)]
 #[derive(Debug, Default, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 struct TransformUniforms {
@@ -60,10 +143,10 @@ struct TransformUniforms {
 }
 
 impl TransformUniforms {
-    fn from_body(body: &physics::RigidBody) -> Self {
+    fn from_pose((position, orientation): (na::Vector4, ga::Rotor4)) -> Self {
         Self {
-            linear: body.orientation.to_matrix(),
-            translation: body.position,
+            linear: orientation.to_matrix(),
+            translation: position,
         }
     }
 }
@@ -188,6 +271,29 @@ pub fn mixtable_bytes() -> Vec<u8> {
         .collect::<Vec<u8>>()
 }
 
+const MATERIAL_SIZE: u32 = 64;
+
+// The texture meshes with materials sample - light and dark rings around the z
+// axis, wobbling so they look like wood grain when cut through at an angle. It's
+// a grey level for the colors to be multiplied by, and repeats every 1 in each
+// texture coordinate.
+fn wood_grain() -> Vec<u8> {
+    let mut texels = Vec::new();
+    for z in 0..MATERIAL_SIZE {
+        for y in 0..MATERIAL_SIZE {
+            for x in 0..MATERIAL_SIZE {
+                let [x, y, z] = [x, y, z].map(|i| i as f32 / MATERIAL_SIZE as f32 * TAU);
+                // Periodic in each coordinate, so the texture tiles without seams
+                let radius = (2.0 - x.cos() - y.cos()) + 0.15 * (z + 2.0 * x).sin();
+                let ring = (radius * 4.0).fract();
+                let grey = (255.0 * (0.55 + 0.45 * ring * ring)) as u8;
+                texels.extend([grey, grey, grey, 255]);
+            }
+        }
+    }
+    texels
+}
+
 pub struct MeshRenderer {
     render_pipeline: wgpu::RenderPipeline,
 
@@ -198,10 +304,17 @@ pub struct MeshRenderer {
 
     camera_uniforms_buffer: wgpu::Buffer,
     camera_uniforms_bind_group: wgpu::BindGroup,
+
+    _material_texture: texture::Texture,
+    material_bind_group: wgpu::BindGroup,
 }
 
 impl MeshRenderer {
-    pub fn new(device: &wgpu::Device, surface_config: &wgpu::SurfaceConfiguration) -> Self {
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        surface_config: &wgpu::SurfaceConfiguration,
+    ) -> Self {
         let mixtable_uniform_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                 entries: &[wgpu::BindGroupLayoutEntry {
@@ -257,6 +370,29 @@ impl MeshRenderer {
                 label: None,
             });
 
+        let material_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            multisampled: false,
+                            view_dimension: wgpu::TextureViewDimension::D3,
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                ],
+                label: None,
+            });
+
         let render_pipeline = wgputil::create_pipeline(
             device,
             surface_config,
@@ -273,9 +409,10 @@ impl MeshRenderer {
                 &mixtable_uniform_bind_group_layout,
                 &camera_uniforms_bind_group_layout,
                 &mesh_bind_group_layout,
+                &material_bind_group_layout,
             ],
             &[wgpu::VertexBufferLayout {
-                array_stride: 192,
+                array_stride: 256,
                 step_mode: wgpu::VertexStepMode::Vertex,
                 attributes: &[
                     wgpu::VertexAttribute {
@@ -338,6 +475,26 @@ impl MeshRenderer {
                         offset: 176,
                         shader_location: 11,
                     },
+                    wgpu::VertexAttribute {
+                        format: wgpu::VertexFormat::Float32x4,
+                        offset: 192,
+                        shader_location: 12,
+                    },
+                    wgpu::VertexAttribute {
+                        format: wgpu::VertexFormat::Float32x4,
+                        offset: 208,
+                        shader_location: 13,
+                    },
+                    wgpu::VertexAttribute {
+                        format: wgpu::VertexFormat::Float32x4,
+                        offset: 224,
+                        shader_location: 14,
+                    },
+                    wgpu::VertexAttribute {
+                        format: wgpu::VertexFormat::Float32x4,
+                        offset: 240,
+                        shader_location: 15,
+                    },
                 ],
             }],
         );
@@ -366,6 +523,23 @@ impl MeshRenderer {
             &camera_uniforms_buffer,
         );
 
+        let material_texture =
+            texture::Texture::from_volume(device, queue, MATERIAL_SIZE, &wood_grain());
+        let material_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &material_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&material_texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&material_texture.sampler),
+                },
+            ],
+            label: None,
+        });
+
         Self {
             render_pipeline,
 
@@ -376,6 +550,9 @@ impl MeshRenderer {
 
             camera_uniforms_buffer,
             camera_uniforms_bind_group,
+
+            _material_texture: material_texture,
+            material_bind_group,
         }
     }
 
@@ -386,27 +563,41 @@ impl MeshRenderer {
         world: &mut hecs::World,
         camera_entity: hecs::Entity,
         aspect: f32,
+        alpha: f32,
     ) {
-        if let Ok((camera, body)) =
-            world.query_one_mut::<(&Camera, &physics::RigidBody)>(camera_entity)
+        let mut camera_position = na::Vector4::zeros();
+        if let Ok((camera, body, previous)) =
+            world.query_one_mut::<(&Camera, &physics::RigidBody, Option<&stepper::PreviousPose>)>(
+                camera_entity,
+            )
         {
+            let pose = stepper::interpolate(body, previous, alpha);
+            camera_position = pose.0;
             queue.write_buffer(
                 &self.camera_uniforms_buffer,
                 0,
-                bytemuck::bytes_of(&CameraUniforms::from_components(camera, aspect, body)),
+                bytemuck::bytes_of(&CameraUniforms::from_components(camera, aspect, pose)),
             );
         }
 
         let mut new_meshes = Vec::new();
-        for (entity, (body, mesh, draw_state)) in world
-            .query::<(&physics::RigidBody, &mesh::Mesh4, &draw_state::DrawState)>()
+        for (entity, (body, previous, mesh, draw_state, lod)) in world
+            .query::<(
+                &physics::RigidBody,
+                Option<&stepper::PreviousPose>,
+                &mesh::Mesh4,
+                &draw_state::DrawState,
+                Option<&Lod>,
+            )>()
             .iter()
         {
-            if let Ok(mesh_buffers) = world.get::<&MeshBuffers>(entity) {
+            let pose = stepper::interpolate(body, previous, alpha);
+            if let Ok(mut mesh_buffers) = world.get::<&mut MeshBuffers>(entity) {
+                mesh_buffers.level = lod.map_or(0, |x| x.level((pose.0 - camera_position).norm()));
                 queue.write_buffer(
                     &mesh_buffers.transform_buffer,
                     0,
-                    bytemuck::bytes_of(&TransformUniforms::from_body(body)),
+                    bytemuck::bytes_of(&TransformUniforms::from_pose(pose)),
                 );
                 queue.write_buffer(
                     &mesh_buffers.draw_state_buffer,
@@ -414,17 +605,24 @@ impl MeshRenderer {
                     bytemuck::bytes_of(&DrawStateUniforms::from_draw_state(draw_state)),
                 );
             } else {
-                let tetrahedra_buffer =
-                    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                        label: None,
-                        contents: bytemuck::cast_slice(mesh.get_buffer_data().as_slice()),
-                        usage: wgpu::BufferUsages::VERTEX,
-                    });
+                let tetrahedra_buffers = std::iter::once(mesh)
+                    .chain(lod.iter().flat_map(|x| x.levels.iter().map(|x| &x.1)))
+                    .map(|mesh| {
+                        (
+                            (mesh.num_tetrahedra * 7) as u32,
+                            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                                label: None,
+                                contents: bytemuck::cast_slice(mesh.get_buffer_data().as_slice()),
+                                usage: wgpu::BufferUsages::VERTEX,
+                            }),
+                        )
+                    })
+                    .collect();
 
                 let transform_buffer =
                     device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
                         label: None,
-                        contents: bytemuck::bytes_of(&TransformUniforms::from_body(body)),
+                        contents: bytemuck::bytes_of(&TransformUniforms::from_pose(pose)),
                         usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
                     });
 
@@ -455,8 +653,8 @@ impl MeshRenderer {
                 new_meshes.push((
                     entity,
                     MeshBuffers {
-                        num_vertices: (mesh.num_tetrahedra * 7) as u32,
-                        tetrahedra_buffer,
+                        tetrahedra_buffers,
+                        level: 0,
                         transform_buffer,
                         draw_state_buffer,
                         bind_group,
@@ -513,11 +711,13 @@ impl MeshRenderer {
         render_pass.set_pipeline(&self.render_pipeline);
         render_pass.set_bind_group(0, &self.mixtable_uniform_bind_group, &[]);
         render_pass.set_bind_group(1, &self.camera_uniforms_bind_group, &[]);
+        render_pass.set_bind_group(3, &self.material_bind_group, &[]);
 
         for (_, mesh_buffers) in mesh_buffers_query.iter() {
             render_pass.set_bind_group(2, &mesh_buffers.bind_group, &[]);
-            render_pass.set_vertex_buffer(0, mesh_buffers.tetrahedra_buffer.slice(..));
-            render_pass.draw(0..mesh_buffers.num_vertices, 0..1);
+            let (num_vertices, buffer) = &mesh_buffers.tetrahedra_buffers[mesh_buffers.level];
+            render_pass.set_vertex_buffer(0, buffer.slice(..));
+            render_pass.draw(0..*num_vertices, 0..1);
         }
     }
 }
@@ -3,7 +3,9 @@ use std::ops::{Mul, MulAssign};
 pub type Matrix4 = nalgebra::Matrix4<f32>;
 pub type Matrix4x3 = nalgebra::SMatrix<f32, 4, 3>;
 pub type Matrix4x6 = nalgebra::SMatrix<f32, 4, 6>;
+pub type Matrix6 = nalgebra::Matrix6<f32>;
 pub type Vector2 = nalgebra::SVector<f32, 2>;
+pub type Vector3 = nalgebra::SVector<f32, 3>;
 pub type Vector4 = nalgebra::SVector<f32, 4>;
 pub type Vector6 = nalgebra::SVector<f32, 6>;
 
@@ -1,65 +1,250 @@
+use crate::collision;
 use crate::constraints;
+use crate::forces;
 use crate::ga;
+use crate::gravity;
 use crate::na;
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PositionCorrection {
+    // Feed position error back into the velocity constraints. Cheap, but adds
+    // energy to the system, making stacks bounce and joints jitter.
+    Baumgarte,
+    // Resolve position error in a separate pass, using pseudo-velocities which are
+    // applied to the positions and then thrown away.
+    SplitImpulse,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Integrator {
+    // Update velocities, then positions using the new velocities. First order, but
+    // stable and good at conserving energy.
+    SymplecticEuler,
+    // Split the velocity update into two half steps either side of the position
+    // update, so position dependent forces like attractors are sampled at both
+    // ends of the step. Second order.
+    VelocityVerlet,
+    // Classic fourth order Runge-Kutta. It's only valid for bodies which aren't
+    // touched by the solver or pushed by forces::apply_forces, so bodies with
+    // contacts, joints, drag or buoyancy fall back to symplectic Euler.
+    RungeKutta4,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum GyroscopicTorque {
+    // Angular velocity is only changed by torques, as though every body's inertia
+    // tensor were the same in every plane
+    None,
+    // Add the gyroscopic term of Euler's equations to the angular acceleration.
+    // Exact for RK4, but it gains energy with the other integrators, and blows up
+    // for long thin bodies spinning quickly.
+    Explicit,
+    // Solve for the angular velocity at the end of the step with a single Newton
+    // iteration, which is stable, but loses a little energy. RK4 always uses the
+    // explicit term.
+    Implicit,
+}
+
+// Everything needed to tune the behaviour of the solver. This is owned by the
+// main loop and passed into each step, so it can be tweaked at runtime.
+#[derive(Debug, Copy, Clone)]
+pub struct SolverSettings {
+    // Length of a single physics tick, and how many substeps to divide it into -
+    // collision detection and constraint solving are done once per substep
+    pub dt: f32,
+    pub substeps: u32,
+    pub integrator: Integrator,
+    pub gyroscopic_torque: GyroscopicTorque,
+
+    pub velocity_iterations: u32,
+    pub position_iterations: u32,
+
+    // The fraction of position error corrected each step - either fed back into
+    // the velocity constraints, or into the separate position pass
+    pub position_correction: PositionCorrection,
+    pub contact_bias_factor: f32,
+    pub joint_bias_factor: f32,
+
+    // Penetration allowed before contacts start pushing back, and closing speed
+    // required before restitution kicks in
+    pub depth_slop: f32,
+    pub rebound_slop: f32,
+
+    pub warm_starting: bool,
+
+    // Maximum number of contacts kept per arbiter, and how close a new contact
+    // may be to an existing one before it's considered a duplicate
+    pub max_contacts: usize,
+    pub contact_merge_distance: f32,
+}
+
+impl Default for SolverSettings {
+    fn default() -> Self {
+        Self {
+            dt: 1.0 / 120.0,
+            substeps: 1,
+            integrator: Integrator::SymplecticEuler,
+            gyroscopic_torque: GyroscopicTorque::None,
+
+            velocity_iterations: 4,
+            position_iterations: 4,
+
+            position_correction: PositionCorrection::Baumgarte,
+
+            contact_bias_factor: 0.1,
+            joint_bias_factor: 0.5,
+
+            depth_slop: 0.001,
+            rebound_slop: 0.1,
+
+            warm_starting: true,
+
+            max_contacts: 6,
+            contact_merge_distance: 0.01,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BodyType {
+    // Moved by forces and constraints
+    Dynamic,
+    // Never moves
+    Static,
+    // Moves according to its velocity or target pose only - it pushes dynamic
+    // bodies, but is never pushed itself
+    Kinematic,
+}
+
 #[derive(Debug, Copy, Clone)]
 pub struct RigidBody {
+    pub body_type: BodyType,
+
     pub position: na::Vector4,
     pub orientation: ga::Rotor4,
+    // For kinematic bodies - if set, the velocities are chosen each step so the
+    // body reaches this position and orientation
+    pub kinematic_target: Option<(na::Vector4, ga::Rotor4)>,
 
     pub mass: f32,
     pub inverse_mass: f32,
-    pub inertia_tensor: f32,
-    pub inverse_inertia_tensor: f32,
+    // The moments of the body in each combination of the 6 basis bivectors, in
+    // local space. The inverse is in world space, and is kept up to date by the
+    // physics step as the body turns.
+    pub inertia_tensor: na::Matrix6,
+    pub inverse_inertia_tensor: na::Matrix6,
 
     pub gravity: f32,
 
     pub velocity: na::Vector4,
     pub force: na::Vector4,
+    // The rate at which velocity decays, per second
     pub linear_damping: f32,
 
     pub angular_velocity: ga::Bivector4,
     pub torque: ga::Bivector4,
+    // The rate at which angular velocity decays, per second
     pub angular_damping: f32,
+
+    // Velocities used only to correct position error, which are cleared after
+    // every step so they don't add momentum
+    pub pseudo_velocity: na::Vector4,
+    pub pseudo_angular_velocity: ga::Bivector4,
 }
 
 impl Default for RigidBody {
     fn default() -> Self {
         Self {
+            body_type: BodyType::Dynamic,
+
             position: na::Vector4::zeros(),
             orientation: ga::Rotor4::identity(),
+            kinematic_target: None,
 
             mass: 1.0,
             inverse_mass: 1.0,
-
-            // Just use a single number as the inertia tensor for now - it's close
-            // enough. In reality, this should be a 6x6 symmetric matrix representing
-            // the moments of the object relative to each of the basis bivectors.
-            inertia_tensor: 1.0,
-            inverse_inertia_tensor: 1.0,
+            inertia_tensor: na::Matrix6::identity(),
+            inverse_inertia_tensor: na::Matrix6::identity(),
 
             gravity: 1.0,
 
             velocity: na::Vector4::zeros(),
             force: na::Vector4::zeros(),
-            linear_damping: 1.0,
+            linear_damping: 0.0,
 
             angular_velocity: ga::Bivector4::zero(),
             torque: ga::Bivector4::zero(),
-            angular_damping: 1.0,
+            angular_damping: 0.0,
+
+            pseudo_velocity: na::Vector4::zeros(),
+            pseudo_angular_velocity: ga::Bivector4::zero(),
         }
     }
 }
 
 impl RigidBody {
+    // Sets the inertia tensor to be the same in every plane, as though the body
+    // were a glome
     pub fn with_mass(self, mass: f32) -> Self {
         Self {
             mass,
-            inverse_mass: 1.0 / mass,
-            inertia_tensor: mass,
-            inverse_inertia_tensor: 1.0 / mass,
+            inverse_mass: if self.is_dynamic() { 1.0 / mass } else { 0.0 },
             ..self
         }
+        .with_inertia(na::Matrix6::identity() * mass)
+    }
+
+    pub fn with_inertia(self, inertia_tensor: na::Matrix6) -> Self {
+        let mut body = Self {
+            inertia_tensor,
+            ..self
+        };
+        body.update_inertia();
+        body
+    }
+
+    // Static and kinematic bodies behave as though they have infinite mass as far
+    // as the solver is concerned
+    pub fn with_body_type(self, body_type: BodyType) -> Self {
+        Self { body_type, ..self }.with_mass(self.mass)
+    }
+
+    pub fn is_dynamic(&self) -> bool {
+        self.body_type == BodyType::Dynamic
+    }
+
+    // Rotate the inverse inertia tensor into world space for the current
+    // orientation
+    pub fn update_inertia(&mut self) {
+        self.inverse_inertia_tensor = if self.is_dynamic() {
+            let rotation = ga::Bivector4::transformation_matrix(&self.orientation.to_matrix());
+            rotation * inverse_inertia(&self.inertia_tensor) * rotation.transpose()
+        } else {
+            na::Matrix6::zeros()
+        };
+    }
+
+    // The inertia tensor in world space
+    pub fn world_inertia_tensor(&self) -> na::Matrix6 {
+        let rotation = ga::Bivector4::transformation_matrix(&self.orientation.to_matrix());
+        rotation * self.inertia_tensor * rotation.transpose()
+    }
+
+    pub fn angular_momentum(&self) -> ga::Bivector4 {
+        self.world_inertia_tensor() * self.angular_velocity
+    }
+
+    // The inverse of the moment of inertia for rotation in a single plane
+    pub fn inverse_inertia_in(&self, plane: ga::Bivector4) -> f32 {
+        match plane.norm() {
+            norm if norm > 0.0 => {
+                plane
+                    .as_vector()
+                    .dot(&(self.inverse_inertia_tensor * plane.as_vector()))
+                    / (norm * norm)
+            }
+            _ => 0.0,
+        }
     }
 
     pub fn get_transform(&self) -> na::Affine4 {
@@ -67,26 +252,270 @@ impl RigidBody {
     }
 }
 
-pub fn apply_physics(dt: f32, constraints: &mut constraints::Constraints, world: &mut hecs::World) {
-    const GRAVITY: na::Vector4 = na::Vector4::new(0.0, -10.0, 0.0, 0.0);
+// The inverse of an inertia tensor. If it's singular, as it is for a body with no
+// thickness in some direction, the planes with no inertia are treated as having
+// infinite inertia instead, so the body can't turn in them at all rather than
+// turning infinitely fast.
+fn inverse_inertia(inertia_tensor: &na::Matrix6) -> na::Matrix6 {
+    inertia_tensor.try_inverse().unwrap_or_else(|| {
+        let eigen = inertia_tensor.symmetric_eigen();
+        let threshold = eigen.eigenvalues.amax() * 1e-6;
+        let inverse = eigen
+            .eigenvalues
+            .map(|x| if x > threshold { 1.0 / x } else { 0.0 });
+        eigen.eigenvectors * na::Matrix6::from_diagonal(&inverse) * eigen.eigenvectors.transpose()
+    })
+}
 
+// The inertia tensor of a solid box with the given mass and side lengths. The
+// moment in the plane of two axes depends on how far the mass is spread along
+// both of them.
+pub fn box_inertia_tensor(mass: f32, size: na::Vector4) -> na::Matrix6 {
+    let s = size.component_mul(&size) * mass / 12.0;
+    na::Matrix6::from_diagonal(&na::Vector6::new(
+        s[0] + s[1],
+        s[0] + s[2],
+        s[0] + s[3],
+        s[1] + s[2],
+        s[1] + s[3],
+        s[2] + s[3],
+    ))
+}
+
+// Advance the world by a single tick of settings.dt, running collision detection
+// and the solver once per substep.
+pub fn step(
+    settings: &SolverSettings,
+    gravity: &gravity::Gravity,
+    constraints: &mut constraints::Constraints,
+    world: &mut hecs::World,
+) {
+    let dt = settings.dt / settings.substeps.max(1) as f32;
+    for _ in 0..settings.substeps.max(1) {
+        collision::do_collisions(settings, constraints, world);
+        apply_physics(dt, settings, gravity, constraints, world);
+    }
+
+    // Forces and torques are accumulated over the whole tick
     for (_, body) in world.query_mut::<&mut RigidBody>() {
-        body.velocity *= body.linear_damping;
-        body.velocity += dt * (GRAVITY * body.gravity + body.force / body.mass);
         body.force = na::Vector4::zeros();
-
-        body.angular_velocity *= body.angular_damping;
-        body.angular_velocity += dt * body.torque / body.mass; // TODO inertia tensor
         body.torque = ga::Bivector4::zero();
     }
+}
+
+pub fn apply_physics(
+    dt: f32,
+    settings: &SolverSettings,
+    gravity: &gravity::Gravity,
+    constraints: &mut constraints::Constraints,
+    world: &mut hecs::World,
+) {
+    let gravity_field = gravity::Field::new(gravity, world);
+    for (_, body) in world.query_mut::<&mut RigidBody>() {
+        body.update_inertia();
+    }
+    let mut constrained = constraints.constrained();
+    constrained.extend(forces::affected(world));
+
+    // Position and orientation changes for bodies integrated with RK4, which are
+    // used in place of their velocities when the positions are updated
+    let mut displacements = std::collections::HashMap::new();
+
+    for (entity, body) in world.query_mut::<&mut RigidBody>() {
+        match body.body_type {
+            BodyType::Dynamic => {}
+            BodyType::Static => continue,
+            BodyType::Kinematic => {
+                if let Some((position, orientation)) = body.kinematic_target {
+                    body.velocity = (position - body.position) / dt;
+                    body.angular_velocity = (body.orientation.reverse() * orientation).log() / dt;
+                }
+                continue;
+            }
+        }
+
+        let gyroscopic = settings.gyroscopic_torque;
+        match settings.integrator {
+            Integrator::RungeKutta4 if !constrained.contains(&entity) => {
+                let gyroscopic = gyroscopic != GyroscopicTorque::None;
+                displacements.insert(
+                    entity,
+                    runge_kutta(dt, &gravity_field, gyroscopic, entity, body),
+                );
+            }
+            integrator => {
+                damp(dt, body);
+                if gyroscopic == GyroscopicTorque::Implicit {
+                    body.angular_velocity = implicit_gyroscopic(dt, body);
+                }
+                let dt = match integrator {
+                    Integrator::VelocityVerlet => 0.5 * dt,
+                    _ => dt,
+                };
+                let gyroscopic = gyroscopic == GyroscopicTorque::Explicit;
+                kick(dt, &gravity_field, gyroscopic, entity, body);
+            }
+        }
+    }
+    forces::apply_forces(dt, &gravity_field, world);
+
+    // The second half of the velocity update, using the forces where the first
+    // half takes the body. It goes before the solver, so the constraints see the
+    // whole of the new velocity, but only the first half moves the body.
+    let mut half_kicks = std::collections::HashMap::new();
+    if settings.integrator == Integrator::VelocityVerlet {
+        let gyroscopic = settings.gyroscopic_torque == GyroscopicTorque::Explicit;
+        for (entity, body) in world.query_mut::<&mut RigidBody>() {
+            if !body.is_dynamic() {
+                continue;
+            }
+            let (velocity, angular_velocity) = (body.velocity, body.angular_velocity);
+            let position = body.position + velocity * dt;
+            body.velocity += 0.5 * dt * acceleration(&gravity_field, entity, body, position);
+            body.angular_velocity +=
+                0.5 * dt * angular_acceleration(body, angular_velocity, gyroscopic);
+            half_kicks.insert(
+                entity,
+                (
+                    body.velocity - velocity,
+                    body.angular_velocity - angular_velocity,
+                ),
+            );
+        }
+    }
 
-    constraints.prepare(dt, world);
-    for _ in 0..4 {
+    constraints.prepare(dt, settings, world);
+    for _ in 0..settings.velocity_iterations {
         constraints.apply(world);
     }
+    constraints.break_joints();
+    if settings.position_correction == PositionCorrection::SplitImpulse {
+        for _ in 0..settings.position_iterations {
+            constraints.apply_position(world);
+        }
+    }
 
-    for (_, body) in world.query_mut::<&mut RigidBody>() {
-        body.position += body.velocity * dt;
-        body.orientation *= ga::Rotor4::from_bivector(body.angular_velocity * dt);
+    for (entity, body) in world.query_mut::<&mut RigidBody>() {
+        if body.body_type == BodyType::Static {
+            continue;
+        }
+        if let Some(&(translation, rotation)) = displacements.get(&entity) {
+            body.position += translation;
+            body.orientation *= ga::Rotor4::from_bivector(rotation);
+        } else {
+            let (kick, angular_kick) = half_kicks
+                .get(&entity)
+                .copied()
+                .unwrap_or((na::Vector4::zeros(), ga::Bivector4::zero()));
+            body.position += (body.velocity + body.pseudo_velocity - kick) * dt;
+            body.orientation *= ga::Rotor4::from_bivector(
+                (body.angular_velocity + body.pseudo_angular_velocity - angular_kick) * dt,
+            );
+        }
+        body.pseudo_velocity = na::Vector4::zeros();
+        body.pseudo_angular_velocity = ga::Bivector4::zero();
+    }
+}
+
+// The acceleration of a body due to gravity and the forces applied to it, if it
+// were at the given position
+fn acceleration(
+    gravity_field: &gravity::Field,
+    entity: hecs::Entity,
+    body: &RigidBody,
+    position: na::Vector4,
+) -> na::Vector4 {
+    gravity_field.acceleration(entity, position) * body.gravity + body.force / body.mass
+}
+
+// The angular acceleration due to the torque applied to a body, if it were
+// spinning with the given angular velocity. This is Euler's equation of rotation,
+// I dw/dt = torque + [w, Iw], where the commutator is the gyroscopic term -
+// angular momentum is conserved, so when the body turns and its inertia tensor
+// changes in world space, its angular velocity has to change to compensate.
+fn angular_acceleration(
+    body: &RigidBody,
+    angular_velocity: ga::Bivector4,
+    gyroscopic: bool,
+) -> ga::Bivector4 {
+    let mut torque = body.torque;
+    if gyroscopic {
+        torque += angular_velocity.commutator(&(body.world_inertia_tensor() * angular_velocity));
     }
+    body.inverse_inertia_tensor * torque
+}
+
+// The angular velocity after a step of free rotation, solving
+// I (w' - w) = dt [w', I w'] for w' with a single Newton iteration, starting
+// from the current angular velocity
+fn implicit_gyroscopic(dt: f32, body: &RigidBody) -> ga::Bivector4 {
+    let inertia = body.world_inertia_tensor();
+    let angular_velocity = body.angular_velocity;
+    let momentum = inertia * angular_velocity;
+    let jacobian = inertia
+        - dt * (angular_velocity.commutator_matrix() * inertia - momentum.commutator_matrix());
+    match jacobian.try_inverse() {
+        Some(inverse) => angular_velocity + dt * (inverse * angular_velocity.commutator(&momentum)),
+        None => angular_velocity,
+    }
+}
+
+fn damp(dt: f32, body: &mut RigidBody) {
+    body.velocity *= (-body.linear_damping * dt).exp();
+    body.angular_velocity *= (-body.angular_damping * dt).exp();
+}
+
+fn kick(
+    dt: f32,
+    gravity_field: &gravity::Field,
+    gyroscopic: bool,
+    entity: hecs::Entity,
+    body: &mut RigidBody,
+) {
+    body.velocity += dt * acceleration(gravity_field, entity, body, body.position);
+    body.angular_velocity += dt * angular_acceleration(body, body.angular_velocity, gyroscopic);
+}
+
+// Advance a body's velocities by a full step with RK4, returning how far it
+// should move and turn. Damping is treated as a force here, rather than applied
+// separately. Each stage turns the body (and so its inertia tensor) by that
+// stage's rotation, but the final rotation is just the weighted average of the
+// angular velocity at each stage, which ignores the fact that rotations don't
+// commute.
+fn runge_kutta(
+    dt: f32,
+    gravity_field: &gravity::Field,
+    gyroscopic: bool,
+    entity: hecs::Entity,
+    body: &mut RigidBody,
+) -> (na::Vector4, ga::Bivector4) {
+    let derivative = |position: na::Vector4, velocity, rotation, angular_velocity| {
+        let mut stage = RigidBody {
+            orientation: body.orientation * ga::Rotor4::from_bivector(rotation),
+            ..*body
+        };
+        stage.update_inertia();
+        (
+            acceleration(gravity_field, entity, &stage, position) - body.linear_damping * velocity,
+            angular_acceleration(&stage, angular_velocity, gyroscopic)
+                - body.angular_damping * angular_velocity,
+        )
+    };
+
+    let zero = ga::Bivector4::zero();
+    let (x1, v1, w1) = (body.position, body.velocity, body.angular_velocity);
+    let (a1, b1) = derivative(x1, v1, zero, w1);
+    let (x2, v2, w2) = (x1 + v1 * 0.5 * dt, v1 + a1 * 0.5 * dt, w1 + b1 * 0.5 * dt);
+    let (a2, b2) = derivative(x2, v2, w1 * 0.5 * dt, w2);
+    let (x3, v3, w3) = (x1 + v2 * 0.5 * dt, v1 + a2 * 0.5 * dt, w1 + b2 * 0.5 * dt);
+    let (a3, b3) = derivative(x3, v3, w2 * 0.5 * dt, w3);
+    let (x4, v4, w4) = (x1 + v3 * dt, v1 + a3 * dt, w1 + b3 * dt);
+    let (a4, b4) = derivative(x4, v4, w3 * dt, w4);
+
+    body.velocity += (a1 + 2.0 * a2 + 2.0 * a3 + a4) * dt / 6.0;
+    body.angular_velocity += (b1 + 2.0 * b2 + 2.0 * b3 + b4) * dt / 6.0;
+    (
+        (v1 + 2.0 * v2 + 2.0 * v3 + v4) * dt / 6.0,
+        (w1 + 2.0 * w2 + 2.0 * w3 + w4) * dt / 6.0,
+    )
 }
@@ -0,0 +1,212 @@
+use crate::mesh;
+use crate::na;
+use crate::na::vec4;
+
+use itertools::Itertools;
+
+// The regular convex 4-polytopes, besides the tesseract. Each is scaled to fit
+// in the unit glome, like the tesseract, and each of its cells is flat shaded in
+// its own color. Since they're convex, Collider::from_mesh4 fits them exactly.
+impl mesh::Mesh4 {
+    // 5 tetrahedral cells - the 4D simplex
+    pub fn five_cell() -> Self {
+        let s5 = 5.0f32.sqrt();
+        let vertices: Vec<na::Vector4> = [
+            vec4(1.0, 1.0, 1.0, -1.0 / s5),
+            vec4(1.0, -1.0, -1.0, -1.0 / s5),
+            vec4(-1.0, 1.0, -1.0, -1.0 / s5),
+            vec4(-1.0, -1.0, 1.0, -1.0 / s5),
+            vec4(0.0, 0.0, 0.0, 4.0 / s5),
+        ]
+        .iter()
+        .map(|x| x.normalize())
+        .collect();
+        // Each cell is opposite a vertex
+        let normals: Vec<na::Vector4> = vertices.iter().map(|x| -x).collect();
+        polytope(&vertices, &normals)
+    }
+
+    // 16 tetrahedral cells - the 4D cross-polytope, dual to the tesseract
+    pub fn sixteen_cell() -> Self {
+        polytope(
+            &generate(&[(vec4(1.0, 0.0, 0.0, 0.0), false)]),
+            &generate(&[(vec4(1.0, 1.0, 1.0, 1.0), false)]),
+        )
+    }
+
+    // 24 octahedral cells. It's self-dual, with no 3D equivalent.
+    pub fn twenty_four_cell() -> Self {
+        polytope(
+            &generate(&[(vec4(1.0, 1.0, 0.0, 0.0), false)]),
+            &generate(&[
+                (vec4(1.0, 0.0, 0.0, 0.0), false),
+                (vec4(0.5, 0.5, 0.5, 0.5), false),
+            ]),
+        )
+    }
+
+    // 600 tetrahedral cells, the 4D equivalent of the icosahedron
+    pub fn six_hundred_cell() -> Self {
+        polytope(&six_hundred_cell_vertices(), &one_twenty_cell_vertices())
+    }
+
+    // 120 dodecahedral cells, the 4D equivalent of the dodecahedron
+    pub fn one_twenty_cell() -> Self {
+        polytope(&one_twenty_cell_vertices(), &six_hundred_cell_vertices())
+    }
+}
+
+const PHI: f32 = 1.618034;
+
+fn six_hundred_cell_vertices() -> Vec<na::Vector4> {
+    generate(&[
+        (vec4(1.0, 1.0, 1.0, 1.0), false),
+        (vec4(2.0, 0.0, 0.0, 0.0), false),
+        (vec4(PHI, 1.0, 1.0 / PHI, 0.0), true),
+    ])
+}
+
+// The centres of the cells of the 600-cell, which are the sets of 4 vertices
+// which are all an edge's length apart
+fn one_twenty_cell_vertices() -> Vec<na::Vector4> {
+    let vertices = six_hundred_cell_vertices();
+    let edge = 1.0 / PHI;
+    let neighbours: Vec<Vec<usize>> = vertices
+        .iter()
+        .map(|a| {
+            (0..vertices.len())
+                .filter(|&b| ((vertices[b] - a).norm() - edge).abs() < 1e-3)
+                .collect()
+        })
+        .collect();
+    let mut centres = Vec::new();
+    for (a, neighbours_a) in neighbours.iter().enumerate() {
+        for (b, c, d) in neighbours_a.iter().copied().tuple_combinations() {
+            if a < b
+                && neighbours[b].contains(&c)
+                && neighbours[b].contains(&d)
+                && neighbours[c].contains(&d)
+            {
+                centres.push((vertices[a] + vertices[b] + vertices[c] + vertices[d]).normalize());
+            }
+        }
+    }
+    centres
+}
+
+// Unit vectors in the direction of every permutation of the coordinates of each
+// seed (or only the even permutations), with every combination of signs
+fn generate(seeds: &[(na::Vector4, bool)]) -> Vec<na::Vector4> {
+    let mut points: Vec<na::Vector4> = Vec::new();
+    for (seed, even) in seeds {
+        for permutation in (0..4).permutations(4) {
+            let inversions = (0..4)
+                .tuple_combinations()
+                .filter(|&(i, j)| permutation[i] > permutation[j])
+                .count();
+            if *even && inversions % 2 == 1 {
+                continue;
+            }
+            for signs in 0..16 {
+                let point = na::Vector4::from_fn(|i, _| {
+                    let x = seed[permutation[i]];
+                    if signs & (1 << i) != 0 {
+                        -x
+                    } else {
+                        x
+                    }
+                })
+                .normalize();
+                // Permuting equal coordinates or flipping the sign of zero gives the
+                // same point again
+                if !points.iter().any(|x| (x - point).norm() < 1e-4) {
+                    points.push(point);
+                }
+            }
+        }
+    }
+    points
+}
+
+// The boundary of the convex hull of the vertices, given the outward normal of
+// each of its cells. Each cell is the set of vertices furthest along its normal.
+fn polytope(vertices: &[na::Vector4], normals: &[na::Vector4]) -> mesh::Mesh4 {
+    let normals: Vec<na::Vector4> = normals.iter().map(|x| x.normalize()).collect();
+    let supports: Vec<f32> = normals
+        .iter()
+        .map(|n| vertices.iter().map(|v| v.dot(n)).fold(f32::MIN, f32::max))
+        .collect();
+    let on_cell = |v: &na::Vector4, cell: usize| v.dot(&normals[cell]) > supports[cell] - 1e-4;
+
+    let mut mesh_vertices = Vec::new();
+    let mut mesh_normals = Vec::new();
+    let mut mesh_colors = Vec::new();
+    let mut indices = Vec::new();
+    for (i, normal) in normals.iter().enumerate() {
+        let mut cell: Vec<na::Vector4> =
+            vertices.iter().filter(|v| on_cell(v, i)).copied().collect();
+
+        let mut tetrahedra = Vec::new();
+        if cell.len() == 4 {
+            tetrahedra.push([0, 1, 2, 3]);
+        } else {
+            // Split the cell into pyramids from its centre to each of its faces,
+            // which are where it meets its neighbours
+            let centre = cell.iter().sum::<na::Vector4>() / cell.len() as f32;
+            for j in (0..normals.len()).filter(|&j| j != i) {
+                let face: Vec<usize> = (0..cell.len()).filter(|&k| on_cell(&cell[k], j)).collect();
+                if face.len() < 3 {
+                    continue;
+                }
+                for (a, b, c) in fan(&cell, &face) {
+                    tetrahedra.push([cell.len(), a, b, c]);
+                }
+            }
+            cell.push(centre);
+        }
+
+        let base = mesh_vertices.len() as u32;
+        for mut tetrahedron in tetrahedra {
+            let v = tetrahedron.map(|k| cell[k]);
+            if mesh::tetrahedron_normal(&v).dot(normal) < 0.0 {
+                tetrahedron.swap(2, 3);
+            }
+            indices.extend(tetrahedron.map(|k| base + k as u32));
+        }
+        mesh_normals.extend(itertools::repeat_n(*normal, cell.len()));
+        mesh_colors.extend(itertools::repeat_n(mesh::palette(i), cell.len()));
+        mesh_vertices.extend(cell);
+    }
+
+    mesh::Mesh4::new(mesh_vertices, mesh_normals, mesh_colors, indices)
+}
+
+// Triangles covering a flat convex polygon, given the indices of its corners in
+// no particular order
+fn fan(points: &[na::Vector4], corners: &[usize]) -> Vec<(usize, usize, usize)> {
+    let centre = corners.iter().map(|&i| points[i]).sum::<na::Vector4>() / corners.len() as f32;
+    let u = (points[corners[0]] - centre).normalize();
+    let v = corners
+        .iter()
+        .map(|&i| {
+            let d = points[i] - centre;
+            d - u * u.dot(&d)
+        })
+        .max_by(|a, b| a.norm().total_cmp(&b.norm()))
+        .unwrap()
+        .normalize();
+    let sorted: Vec<usize> = corners
+        .iter()
+        .copied()
+        .sorted_by(|&a, &b| {
+            let angle = |i: usize| {
+                let d = points[i] - centre;
+                d.dot(&v).atan2(d.dot(&u))
+            };
+            angle(a).total_cmp(&angle(b))
+        })
+        .collect();
+    (1..sorted.len() - 1)
+        .map(|k| (sorted[0], sorted[k], sorted[k + 1]))
+        .collect()
+}
@@ -0,0 +1,323 @@
+use crate::mesh;
+use crate::na;
+
+use std::collections::HashMap;
+
+// Finds exactly where rays meet a mesh's boundary, rather than its convex hull.
+// The cells are kept in a bounding volume hierarchy so that only the few near
+// the ray are tested, and each cell knows its neighbours across its faces, for
+// walking over the boundary from wherever a ray hit it.
+pub struct MeshIndex {
+    pub adjacency: Adjacency,
+    nodes: Vec<Node>,
+    // Cell indices, in the order the leaves refer to them
+    cells: Vec<usize>,
+}
+
+// Which cell is on the other side of each face of each cell. Face i of a cell is
+// the one opposite its vertex i, and is half-face 4 * cell + i. Meshes often
+// give each cell its own copies of its vertices, so faces are matched by where
+// their vertices are rather than by index.
+pub struct Adjacency {
+    opposite: Vec<Option<usize>>,
+}
+
+impl Adjacency {
+    pub fn new(mesh: &mesh::Mesh4) -> Self {
+        let welded = mesh::weld(&mesh.vertices);
+
+        let mut faces: HashMap<[usize; 3], Vec<usize>> = HashMap::new();
+        for (cell, tetrahedron) in mesh.indices.chunks(4).enumerate() {
+            for i in 0..4 {
+                let mut face = [1, 2, 3].map(|j| welded[tetrahedron[(i + j) % 4] as usize]);
+                face.sort_unstable();
+                faces.entry(face).or_default().push(4 * cell + i);
+            }
+        }
+        // Faces on more than two cells have no one opposite, the same as faces on
+        // the edge of a hole
+        let mut opposite = vec![None; mesh.indices.len()];
+        for half_faces in faces.values() {
+            if let [a, b] = half_faces[..] {
+                opposite[a] = Some(b);
+                opposite[b] = Some(a);
+            }
+        }
+        Self { opposite }
+    }
+
+    pub fn opposite(&self, half_face: usize) -> Option<usize> {
+        self.opposite[half_face]
+    }
+
+    pub fn neighbours(&self, cell: usize) -> impl Iterator<Item = usize> + '_ {
+        (0..4).filter_map(move |i| self.opposite(4 * cell + i).map(|x| x / 4))
+    }
+}
+
+struct Node {
+    min: na::Vector4,
+    max: na::Vector4,
+    contents: Contents,
+}
+
+enum Contents {
+    Children(usize, usize),
+    // A range of MeshIndex::cells
+    Cells(usize, usize),
+}
+
+// Where a ray met the boundary
+#[derive(Debug, Clone, Copy)]
+pub struct Hit {
+    pub cell: usize,
+    // How far along the ray, in multiples of its direction
+    pub t: f32,
+    // Weights of the cell's vertices, in the order of its indices
+    pub barycentric: na::Vector4,
+    pub position: na::Vector4,
+    // Interpolated from the vertices, like they are when drawing
+    pub normal: na::Vector4,
+    pub color: na::Vector4,
+}
+
+const LEAF_SIZE: usize = 4;
+
+impl MeshIndex {
+    pub fn new(mesh: &mesh::Mesh4) -> Self {
+        let bounds: Vec<(na::Vector4, na::Vector4)> = mesh
+            .indices
+            .chunks(4)
+            .map(|tetrahedron| {
+                let v = |i: usize| mesh.vertices[tetrahedron[i] as usize];
+                (1..4).fold((v(0), v(0)), |(min, max), i| {
+                    (min.inf(&v(i)), max.sup(&v(i)))
+                })
+            })
+            .collect();
+        let mut index = Self {
+            adjacency: Adjacency::new(mesh),
+            nodes: Vec::new(),
+            cells: (0..bounds.len()).collect(),
+        };
+        if !bounds.is_empty() {
+            index.build(&bounds, 0, bounds.len());
+        }
+        index
+    }
+
+    // Makes a node for the cells in the range, splitting them in half along the
+    // axis their centres are most spread out on, and returns where it went
+    fn build(&mut self, bounds: &[(na::Vector4, na::Vector4)], start: usize, end: usize) -> usize {
+        let cells = &mut self.cells[start..end];
+        let (min, max) = cells.iter().fold(
+            (
+                na::Vector4::repeat(f32::INFINITY),
+                na::Vector4::repeat(-f32::INFINITY),
+            ),
+            |(min, max), &cell| (min.inf(&bounds[cell].0), max.sup(&bounds[cell].1)),
+        );
+        let node = self.nodes.len();
+        self.nodes.push(Node {
+            min,
+            max,
+            contents: Contents::Cells(start, end),
+        });
+        if end - start <= LEAF_SIZE {
+            return node;
+        }
+
+        let centre = |cell: usize| bounds[cell].0 + bounds[cell].1;
+        let (low, high) = cells.iter().fold(
+            (
+                na::Vector4::repeat(f32::INFINITY),
+                na::Vector4::repeat(-f32::INFINITY),
+            ),
+            |(low, high), &cell| (low.inf(&centre(cell)), high.sup(&centre(cell))),
+        );
+        let axis = (high - low).imax();
+        let middle = (end - start) / 2;
+        cells.select_nth_unstable_by(middle, |&a, &b| centre(a)[axis].total_cmp(&centre(b)[axis]));
+        let a = self.build(bounds, start, start + middle);
+        let b = self.build(bounds, start + middle, end);
+        self.nodes[node].contents = Contents::Children(a, b);
+        node
+    }
+
+    // The nearest hit in front of the origin, with the ray in the mesh's own
+    // space
+    pub fn cast_ray(
+        &self,
+        mesh: &mesh::Mesh4,
+        origin: na::Vector4,
+        direction: na::Vector4,
+    ) -> Option<Hit> {
+        let mut nearest: Option<Hit> = None;
+        let mut stack = if self.nodes.is_empty() {
+            vec![]
+        } else {
+            vec![0]
+        };
+        while let Some(node) = stack.pop() {
+            let node = &self.nodes[node];
+            let limit = nearest.map_or(f32::INFINITY, |x| x.t);
+            if !crosses_box(origin, direction, node.min, node.max, limit) {
+                continue;
+            }
+            match node.contents {
+                Contents::Children(a, b) => stack.extend([a, b]),
+                Contents::Cells(start, end) => {
+                    for &cell in self.cells[start..end].iter() {
+                        if let Some(hit) = cast_ray_cell(mesh, cell, origin, direction) {
+                            if hit.t < nearest.map_or(f32::INFINITY, |x| x.t) {
+                                nearest = Some(hit);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        nearest
+    }
+
+    // Paints the vertices within radius of a hit, walking out over the boundary
+    // from the cell it was on, so that only the surface around it is painted
+    // and not whatever else happens to be close by in 4D
+    pub fn paint(&self, mesh: &mut mesh::Mesh4, hit: &Hit, radius: f32, color: na::Vector4) {
+        let corners = |cell: usize| [0, 1, 2, 3].map(|i| mesh.indices[4 * cell + i] as usize);
+        let centre: na::Vector4 = corners(hit.cell)
+            .iter()
+            .zip(hit.barycentric.iter())
+            .map(|(&i, &weight)| mesh.vertices[i] * weight)
+            .sum();
+        let mut visited = vec![false; mesh.num_tetrahedra];
+        visited[hit.cell] = true;
+        let mut stack = vec![hit.cell];
+        while let Some(cell) = stack.pop() {
+            for i in corners(cell) {
+                if (mesh.vertices[i] - centre).norm() <= radius {
+                    mesh.colors[i] = color;
+                }
+            }
+            for neighbour in self.adjacency.neighbours(cell) {
+                if !visited[neighbour]
+                    && corners(neighbour)
+                        .iter()
+                        .any(|&i| (mesh.vertices[i] - centre).norm() <= radius)
+                {
+                    visited[neighbour] = true;
+                    stack.push(neighbour);
+                }
+            }
+        }
+    }
+}
+
+// Whether the ray passes through the box before getting as far as limit
+fn crosses_box(
+    origin: na::Vector4,
+    direction: na::Vector4,
+    min: na::Vector4,
+    max: na::Vector4,
+    limit: f32,
+) -> bool {
+    let (mut near, mut far) = (0.0_f32, limit);
+    for i in 0..4 {
+        if direction[i] == 0.0 {
+            if origin[i] < min[i] || origin[i] > max[i] {
+                return false;
+            }
+            continue;
+        }
+        let a = (min[i] - origin[i]) / direction[i];
+        let b = (max[i] - origin[i]) / direction[i];
+        near = near.max(a.min(b));
+        far = far.min(a.max(b));
+    }
+    near <= far
+}
+
+// Solves for where the ray meets the cell's hyperplane, in terms of the cell's
+// edges, which gives the barycentric coordinates and distance together
+fn cast_ray_cell(
+    mesh: &mesh::Mesh4,
+    cell: usize,
+    origin: na::Vector4,
+    direction: na::Vector4,
+) -> Option<Hit> {
+    const EPSILON: f32 = 1e-6;
+    let indices = [0, 1, 2, 3].map(|i| mesh.indices[4 * cell + i] as usize);
+    let v = indices.map(|i| mesh.vertices[i]);
+    let solution = na::Matrix4::from_columns(&[v[1] - v[0], v[2] - v[0], v[3] - v[0], -direction])
+        .lu()
+        .solve(&(origin - v[0]))?;
+    let t = solution[3];
+    let barycentric = na::vec4(
+        1.0 - solution[0] - solution[1] - solution[2],
+        solution[0],
+        solution[1],
+        solution[2],
+    );
+    if t < 0.0 || barycentric.iter().any(|&x| x < -EPSILON) {
+        return None;
+    }
+    let interpolate = |values: &[na::Vector4]| -> na::Vector4 {
+        (0..4).map(|i| values[indices[i]] * barycentric[i]).sum()
+    };
+    Some(Hit {
+        cell,
+        t,
+        barycentric,
+        position: origin + direction * t,
+        // The vertex normals can cancel out, and then there's only the cell's
+        normal: interpolate(&mesh.normals)
+            .try_normalize(0.0)
+            .unwrap_or_else(|| mesh::tetrahedron_normal(&v).normalize()),
+        color: interpolate(&mesh.colors),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adjacency() {
+        let cube = mesh::Mesh4::cube();
+        let adjacency = Adjacency::new(&cube);
+        for half_face in 0..cube.indices.len() {
+            let opposite = adjacency.opposite(half_face).unwrap();
+            assert_ne!(opposite / 4, half_face / 4);
+            assert_eq!(adjacency.opposite(opposite), Some(half_face));
+        }
+    }
+
+    #[test]
+    fn cast_ray() {
+        // A tesseract with a notch cut out of its +x side, which a ray along x
+        // goes into, past where the convex hull would stop it
+        let cube = mesh::Mesh4::cube();
+        let notch = mesh::Mesh4::cube().transformed(&na::Affine4::from_pos(
+            na::vec4(0.5, 0.0, 0.0, 0.0),
+            na::Matrix4::identity(),
+            na::Vector4::repeat(0.5),
+        ));
+        let mesh = cube.difference(&notch);
+        let index = MeshIndex::new(&mesh);
+
+        let origin = na::vec4(2.0, 0.1, 0.05, 0.02);
+        let hit = index.cast_ray(&mesh, origin, -na::Vector4::x()).unwrap();
+        assert!((hit.t - 1.75).abs() < 1e-5);
+        assert!((hit.position - na::vec4(0.25, 0.1, 0.05, 0.02)).norm() < 1e-5);
+        assert!((hit.normal - na::Vector4::x()).norm() < 1e-5);
+        assert!((hit.barycentric.sum() - 1.0).abs() < 1e-5);
+        let corners = (0..4).map(|i| mesh.vertices[mesh.indices[4 * hit.cell + i] as usize]);
+        let position: na::Vector4 = corners
+            .zip(hit.barycentric.iter())
+            .map(|(x, &w)| x * w)
+            .sum();
+        assert!((position - hit.position).norm() < 1e-5);
+
+        assert!(index.cast_ray(&mesh, origin, na::Vector4::x()).is_none());
+    }
+}
@@ -68,7 +68,7 @@ impl Renderer {
             wgpu::TextureFormat::Depth32Float,
         );
 
-        let mesh_renderer = mesh_renderer::MeshRenderer::new(&device, &surface_config);
+        let mesh_renderer = mesh_renderer::MeshRenderer::new(&device, &queue, &surface_config);
         let sprite_renderer = sprite_renderer::SpriteRenderer::new(&device, &surface_config);
 
         Self {
@@ -99,13 +99,20 @@ impl Renderer {
         }
     }
 
-    pub fn update_buffers(&mut self, world: &mut hecs::World, camera_entity: hecs::Entity) {
+    // Bodies are drawn alpha of the way between their previous and current poses
+    pub fn update_buffers(
+        &mut self,
+        world: &mut hecs::World,
+        camera_entity: hecs::Entity,
+        alpha: f32,
+    ) {
         self.mesh_renderer.update_buffers(
             &self.device,
             &self.queue,
             world,
             camera_entity,
             self.surface_config.width as f32 / self.surface_config.height as f32,
+            alpha,
         );
         self.sprite_renderer
             .update_buffers(&self.device, &self.queue, self.size, world);
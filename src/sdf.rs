@@ -0,0 +1,324 @@
+use crate::mesh;
+use crate::na;
+
+use itertools::Itertools;
+use std::collections::HashMap;
+
+// A shape given by its signed distance function - the distance to its boundary,
+// negative inside. Primitives are centred on the origin, and are combined and
+// moved around by the methods below. The combinations only give a bound on the
+// distance rather than the exact distance, which is fine for meshing.
+#[derive(Debug, Clone)]
+pub enum Sdf {
+    Glome {
+        radius: f32,
+    },
+    Cuboid {
+        half_size: na::Vector4,
+    },
+    // A ball in xyz, extruded along w
+    Spherinder {
+        radius: f32,
+        half_length: f32,
+    },
+    // The product of a disk in xy and a disk in zw
+    Duocylinder {
+        radii: [f32; 2],
+    },
+    // Everything on the side of the plane the normal points away from
+    HalfSpace {
+        normal: na::Vector4,
+        offset: f32,
+    },
+    // The shape is evaluated at the inverse of the transform
+    Transformed {
+        inverse: na::Affine4,
+        shape: Box<Sdf>,
+    },
+    Union(Box<Sdf>, Box<Sdf>),
+    Subtract(Box<Sdf>, Box<Sdf>),
+    Intersect(Box<Sdf>, Box<Sdf>),
+    // A union with the seam filled in, over about radius
+    Blend {
+        a: Box<Sdf>,
+        b: Box<Sdf>,
+        radius: f32,
+    },
+}
+
+impl Sdf {
+    pub fn glome(radius: f32) -> Self {
+        Self::Glome { radius }
+    }
+
+    pub fn cuboid(half_size: na::Vector4) -> Self {
+        Self::Cuboid { half_size }
+    }
+
+    pub fn spherinder(radius: f32, half_length: f32) -> Self {
+        Self::Spherinder {
+            radius,
+            half_length,
+        }
+    }
+
+    pub fn duocylinder(radii: [f32; 2]) -> Self {
+        Self::Duocylinder { radii }
+    }
+
+    pub fn half_space(normal: na::Vector4, offset: f32) -> Self {
+        Self::HalfSpace {
+            normal: normal.normalize(),
+            offset,
+        }
+    }
+
+    // Only rotations and translations keep the distances right
+    pub fn transformed(self, transform: &na::Affine4) -> Self {
+        Self::Transformed {
+            inverse: transform.inverse(),
+            shape: Box::new(self),
+        }
+    }
+
+    pub fn translated(self, offset: na::Vector4) -> Self {
+        self.transformed(&na::Affine4::from_pos(
+            offset,
+            na::Matrix4::identity(),
+            na::Vector4::repeat(1.0),
+        ))
+    }
+
+    pub fn union(self, other: Self) -> Self {
+        Self::Union(Box::new(self), Box::new(other))
+    }
+
+    pub fn subtract(self, other: Self) -> Self {
+        Self::Subtract(Box::new(self), Box::new(other))
+    }
+
+    pub fn intersect(self, other: Self) -> Self {
+        Self::Intersect(Box::new(self), Box::new(other))
+    }
+
+    pub fn blend(self, other: Self, radius: f32) -> Self {
+        Self::Blend {
+            a: Box::new(self),
+            b: Box::new(other),
+            radius,
+        }
+    }
+
+    pub fn distance(&self, p: na::Vector4) -> f32 {
+        match self {
+            Self::Glome { radius } => p.norm() - radius,
+            Self::Cuboid { half_size } => {
+                let q = p.abs() - half_size;
+                q.sup(&na::Vector4::zeros()).norm() + q.max().min(0.0)
+            }
+            Self::Spherinder {
+                radius,
+                half_length,
+            } => extrusion(
+                p.fixed_rows::<3>(0).norm() - radius,
+                p[3].abs() - half_length,
+            ),
+            Self::Duocylinder { radii } => extrusion(
+                p.fixed_rows::<2>(0).norm() - radii[0],
+                p.fixed_rows::<2>(2).norm() - radii[1],
+            ),
+            Self::HalfSpace { normal, offset } => normal.dot(&p) - offset,
+            Self::Transformed { inverse, shape } => shape.distance(inverse * p),
+            Self::Union(a, b) => a.distance(p).min(b.distance(p)),
+            Self::Subtract(a, b) => a.distance(p).max(-b.distance(p)),
+            Self::Intersect(a, b) => a.distance(p).max(b.distance(p)),
+            Self::Blend { a, b, radius } => {
+                let (a, b) = (a.distance(p), b.distance(p));
+                let h = (0.5 + 0.5 * (b - a) / radius).clamp(0.0, 1.0);
+                b + (a - b) * h - radius * h * (1.0 - h)
+            }
+        }
+    }
+
+    // By central differences, so it doesn't need to be worked out for each shape
+    pub fn gradient(&self, p: na::Vector4) -> na::Vector4 {
+        const EPSILON: f32 = 1e-3;
+        na::Vector4::from_fn(|i, _| {
+            let offset = na::Vector4::ith(i, EPSILON);
+            self.distance(p + offset) - self.distance(p - offset)
+        }) / (2.0 * EPSILON)
+    }
+}
+
+// The distance to a shape whose cross section has distance a, stretched out so
+// that its other distance is b
+fn extrusion(a: f32, b: f32) -> f32 {
+    na::Vector2::new(a.max(0.0), b.max(0.0)).norm() + a.max(b).min(0.0)
+}
+
+impl mesh::Mesh4 {
+    // Marching tetrahedra, one dimension up. The box from min to max is cut into
+    // a grid of resolution cubes per side, and each cube into 24 simplices the
+    // same way, so that neighbours meet face to face. The distance is
+    // interpolated linearly along each simplex edge, which makes the boundary
+    // within a simplex either a tetrahedron or a triangular prism. Normals come
+    // from the gradient, and each vertex gets the color of the axis its normal is
+    // closest to.
+    pub fn from_sdf(sdf: &Sdf, min: na::Vector4, max: na::Vector4, resolution: usize) -> Self {
+        let size = resolution + 1;
+        let id = |i: [usize; 4]| ((i[0] * size + i[1]) * size + i[2]) * size + i[3];
+        let corner = |i: [usize; 4]| {
+            min + (max - min).component_mul(&na::Vector4::from_fn(|axis, _| {
+                i[axis] as f32 / resolution as f32
+            }))
+        };
+        let mut distances = vec![0.0; size.pow(4)];
+        for x in 0..size {
+            for y in 0..size {
+                for z in 0..size {
+                    for w in 0..size {
+                        distances[id([x, y, z, w])] = sdf.distance(corner([x, y, z, w]));
+                    }
+                }
+            }
+        }
+
+        let mut mesher = Mesher {
+            sdf,
+            crossings: HashMap::new(),
+            vertices: Vec::new(),
+            normals: Vec::new(),
+            colors: Vec::new(),
+            indices: Vec::new(),
+        };
+        // The orders to step along the axes from one corner of a cube to the
+        // opposite one
+        let orders: Vec<Vec<usize>> = (0..4).permutations(4).collect();
+        for x in 0..resolution {
+            for y in 0..resolution {
+                for z in 0..resolution {
+                    for w in 0..resolution {
+                        let start = [x, y, z, w];
+                        let cube: Vec<f32> = (0..16)
+                            .map(|bits| {
+                                distances
+                                    [id([0, 1, 2, 3].map(|axis| start[axis] + (bits >> axis & 1)))]
+                            })
+                            .collect();
+                        if cube.iter().all(|&d| d < 0.0) || cube.iter().all(|&d| d >= 0.0) {
+                            continue;
+                        }
+                        for order in orders.iter() {
+                            let mut i = start;
+                            let mut simplex = [(0, na::Vector4::zeros(), 0.0); 5];
+                            for n in 0..5 {
+                                if n > 0 {
+                                    i[order[n - 1]] += 1;
+                                }
+                                simplex[n] = (id(i), corner(i), distances[id(i)]);
+                            }
+                            mesher.simplex(&simplex);
+                        }
+                    }
+                }
+            }
+        }
+        mesh::Mesh4::new(
+            mesher.vertices,
+            mesher.normals,
+            mesher.colors,
+            mesher.indices,
+        )
+    }
+}
+
+struct Mesher<'a> {
+    sdf: &'a Sdf,
+    // Boundary vertices are shared between simplices, keyed by the grid edge
+    // they're on
+    crossings: HashMap<(usize, usize), u32>,
+    vertices: Vec<na::Vector4>,
+    normals: Vec<na::Vector4>,
+    colors: Vec<na::Vector4>,
+    indices: Vec<u32>,
+}
+
+type Corner = (usize, na::Vector4, f32);
+
+impl<'a> Mesher<'a> {
+    // Crossings right next to a corner are moved onto it, and shared by every
+    // edge from that corner. Otherwise they'd be separate vertices in almost the
+    // same place, making slivers which could be dropped on one side of a face
+    // and not the other.
+    fn crossing(&mut self, a: &Corner, b: &Corner) -> u32 {
+        const SNAP: f32 = 1e-3;
+        let t = a.2 / (a.2 - b.2);
+        let (key, position) = if t < SNAP {
+            ((a.0, a.0), a.1)
+        } else if t > 1.0 - SNAP {
+            ((b.0, b.0), b.1)
+        } else {
+            ((a.0.min(b.0), a.0.max(b.0)), a.1 + (b.1 - a.1) * t)
+        };
+        if let Some(&index) = self.crossings.get(&key) {
+            return index;
+        }
+        let normal = self.sdf.gradient(position).normalize();
+        let axis = normal.iamax();
+        let index = self.vertices.len() as u32;
+        self.vertices.push(position);
+        self.normals.push(normal);
+        self.colors.push(mesh::palette(axis));
+        self.crossings.insert(key, index);
+        index
+    }
+
+    fn simplex(&mut self, simplex: &[Corner; 5]) {
+        let (mut inside, mut outside): (Vec<Corner>, Vec<Corner>) =
+            simplex.iter().partition(|corner| corner.2 < 0.0);
+        if inside.is_empty() || outside.is_empty() {
+            return;
+        }
+        // The boundary is flat within the simplex, and faces from the inside
+        // corners to the outside ones
+        let centre = |corners: &[Corner]| {
+            corners.iter().map(|c| c.1).sum::<na::Vector4>() / corners.len() as f32
+        };
+        let outward = centre(&outside) - centre(&inside);
+
+        // Make the smaller side the first, so there are either 1 or 2 corners
+        // on it, and sort both by id so neighbouring simplices split the prisms'
+        // sides the same way
+        if inside.len() > outside.len() {
+            std::mem::swap(&mut inside, &mut outside);
+        }
+        inside.sort_by_key(|c| c.0);
+        outside.sort_by_key(|c| c.0);
+        let edges: Vec<Vec<u32>> = inside
+            .iter()
+            .map(|a| outside.iter().map(|b| self.crossing(a, b)).collect())
+            .collect();
+        if edges.len() == 1 {
+            self.push_tetrahedron(
+                [edges[0][0], edges[0][1], edges[0][2], edges[0][3]],
+                outward,
+            );
+        } else {
+            let (bottom, top) = (&edges[0], &edges[1]);
+            self.push_tetrahedron([bottom[0], bottom[1], bottom[2], top[2]], outward);
+            self.push_tetrahedron([bottom[0], bottom[1], top[1], top[2]], outward);
+            self.push_tetrahedron([bottom[0], top[0], top[1], top[2]], outward);
+        }
+    }
+
+    fn push_tetrahedron(&mut self, mut tetrahedron: [u32; 4], outward: na::Vector4) {
+        let normal = mesh::tetrahedron_normal(&tetrahedron.map(|i| self.vertices[i as usize]));
+        if normal.norm() < 1e-12 {
+            return;
+        }
+        if normal.dot(&outward) < 0.0 {
+            tetrahedron.swap(2, 3);
+        }
+        self.indices.extend(tetrahedron);
+    }
+}
@@ -0,0 +1,371 @@
+use crate::mesh;
+use crate::na;
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashSet};
+
+// The sum of squared distances from a point to a set of hyperplanes, each
+// weighted by the volume of the cell it came from - a quadric error metric, as
+// in Garland and Heckbert, one dimension up. It's v.a.v + 2 b.v + c.
+#[derive(Debug, Clone, Copy)]
+struct Quadric {
+    a: na::Matrix4,
+    b: na::Vector4,
+    c: f32,
+}
+
+impl Quadric {
+    fn zero() -> Self {
+        Self {
+            a: na::Matrix4::zeros(),
+            b: na::Vector4::zeros(),
+            c: 0.0,
+        }
+    }
+
+    fn from_cell(vertices: &[na::Vector4; 4]) -> Self {
+        let normal = mesh::tetrahedron_normal(vertices);
+        let volume = normal.norm() / 6.0;
+        let normal = normal.try_normalize(0.0).unwrap_or_else(na::Vector4::zeros);
+        let offset = normal.dot(&vertices[0]);
+        Self {
+            a: normal * normal.transpose() * volume,
+            b: -normal * offset * volume,
+            c: offset * offset * volume,
+        }
+    }
+
+    fn add(&self, other: &Self) -> Self {
+        Self {
+            a: self.a + other.a,
+            b: self.b + other.b,
+            c: self.c + other.c,
+        }
+    }
+
+    fn error(&self, v: &na::Vector4) -> f32 {
+        (v.dot(&(self.a * v)) + 2.0 * self.b.dot(v) + self.c).max(0.0)
+    }
+
+    // The root mean square distance to the hyperplanes, weighted the same way.
+    // The trace of a is the total weight, as the normals are unit length.
+    fn distance(&self, v: &na::Vector4) -> f32 {
+        let weight = self.a.trace();
+        if weight > 0.0 {
+            (self.error(v) / weight).sqrt()
+        } else {
+            0.0
+        }
+    }
+}
+
+impl mesh::Mesh4 {
+    // Collapses edges of the boundary, cheapest first, until there are at most
+    // target tetrahedra or nothing more can go without turning a cell inside out
+    // or moving the boundary further than max_distance from the cells it started
+    // from. Vertices in the same place are collapsed together, so meshes with a
+    // copy of each vertex per cell keep their sharp edges as long as they can.
+    // Each vertex keeps its own normal, color and material as it moves, and
+    // along with the new mesh comes which vertex of this one each of its
+    // vertices was.
+    pub fn simplified(&self, target: usize, max_distance: f32) -> (Self, Vec<usize>) {
+        let mut simplifier = Simplifier::new(self);
+        simplifier.run(target, max_distance);
+        simplifier.mesh()
+    }
+}
+
+// Where an edge could be collapsed, and what it would cost. Non-negative floats
+// order the same way as their bits, which saves wrapping them.
+type Candidate = (Reverse<u32>, usize, usize, u32, u32);
+
+struct Simplifier<'a> {
+    mesh: &'a mesh::Mesh4,
+    // Vertices in the same place share a point, and a collapsed point forwards
+    // to the one it was collapsed into
+    point_of: Vec<usize>,
+    forward: Vec<usize>,
+    positions: Vec<na::Vector4>,
+    quadrics: Vec<Quadric>,
+    // Bumped whenever a point moves, to tell which candidates are out of date
+    stamps: Vec<u32>,
+    point_cells: Vec<Vec<usize>>,
+    alive: Vec<bool>,
+    count: usize,
+    heap: BinaryHeap<Candidate>,
+}
+
+impl<'a> Simplifier<'a> {
+    fn new(mesh: &'a mesh::Mesh4) -> Self {
+        let point_of = mesh::weld(&mesh.vertices);
+        let mut positions = vec![na::Vector4::zeros(); point_of.iter().max().map_or(0, |x| x + 1)];
+        for (vertex, &point) in mesh.vertices.iter().zip(point_of.iter()) {
+            positions[point] = *vertex;
+        }
+
+        let count = mesh.num_tetrahedra;
+        let mut quadrics = vec![Quadric::zero(); positions.len()];
+        let mut point_cells = vec![Vec::new(); positions.len()];
+        for (cell, tetrahedron) in mesh.indices.chunks(4).enumerate() {
+            let quadric =
+                Quadric::from_cell(&[0, 1, 2, 3].map(|i| mesh.vertices[tetrahedron[i] as usize]));
+            for &i in tetrahedron {
+                let point = point_of[i as usize];
+                quadrics[point] = quadrics[point].add(&quadric);
+                if !point_cells[point].contains(&cell) {
+                    point_cells[point].push(cell);
+                }
+            }
+        }
+
+        let mut simplifier = Self {
+            mesh,
+            point_of,
+            forward: (0..positions.len()).collect(),
+            stamps: vec![0; positions.len()],
+            positions,
+            quadrics,
+            point_cells,
+            alive: vec![true; count],
+            count,
+            heap: BinaryHeap::new(),
+        };
+        for point in 0..simplifier.positions.len() {
+            simplifier.push_edges(point, point);
+        }
+        simplifier
+    }
+
+    fn find(&self, mut point: usize) -> usize {
+        while self.forward[point] != point {
+            point = self.forward[point];
+        }
+        point
+    }
+
+    fn cell_points(&self, cell: usize) -> [usize; 4] {
+        [0, 1, 2, 3].map(|i| self.find(self.point_of[self.mesh.indices[4 * cell + i] as usize]))
+    }
+
+    // Where to put the point two are collapsed into - wherever has the least
+    // error out of the ends and the middle of the edge, and the best place of
+    // all if there is one nearby
+    fn target(&self, a: usize, b: usize) -> (na::Vector4, f32) {
+        let quadric = self.quadrics[a].add(&self.quadrics[b]);
+        let (p, q) = (self.positions[a], self.positions[b]);
+        let middle = (p + q) / 2.0;
+        let mut candidates = vec![p, q, middle];
+        if let Some(inverse) = quadric.a.try_inverse() {
+            let best = -(inverse * quadric.b);
+            if (best - middle).norm() <= (q - p).norm() {
+                candidates.push(best);
+            }
+        }
+        candidates
+            .into_iter()
+            .map(|x| (x, quadric.error(&x)))
+            .min_by(|x, y| x.1.total_cmp(&y.1))
+            .unwrap()
+    }
+
+    // Queues up the edges from the point to others from start on
+    fn push_edges(&mut self, point: usize, start: usize) {
+        let mut others: Vec<usize> = self.point_cells[point]
+            .iter()
+            .flat_map(|&cell| self.cell_points(cell))
+            .filter(|&x| x != point && x >= start)
+            .collect();
+        others.sort_unstable();
+        others.dedup();
+        for other in others {
+            let (_, error) = self.target(point, other);
+            self.heap.push((
+                Reverse(error.to_bits()),
+                point,
+                other,
+                self.stamps[point],
+                self.stamps[other],
+            ));
+        }
+    }
+
+    // The link condition - collapsing an edge keeps the boundary a manifold as
+    // long as everything around both its ends is around the edge itself
+    fn keeps_manifold(&self, a: usize, b: usize, cells: &[usize]) -> bool {
+        let mut links = [HashSet::new(), HashSet::new(), HashSet::new()];
+        for &cell in cells {
+            let points = self.cell_points(cell);
+            let link = match (points.contains(&a), points.contains(&b)) {
+                (true, true) => 0,
+                (true, false) => 1,
+                _ => 2,
+            };
+            let mut others: Vec<usize> = points.into_iter().filter(|&x| x != a && x != b).collect();
+            others.sort_unstable();
+            others.dedup();
+            for subset in 1..1 << others.len() {
+                let simplex: Vec<usize> = (0..others.len())
+                    .filter(|i| subset >> i & 1 == 1)
+                    .map(|i| others[i])
+                    .collect();
+                links[link].insert(simplex);
+            }
+        }
+        links[1]
+            .intersection(&links[2])
+            .all(|simplex| links[0].contains(simplex))
+    }
+
+    fn run(&mut self, target: usize, max_distance: f32) {
+        while self.count > target {
+            let Some((_, a, b, a_stamp, b_stamp)) = self.heap.pop() else {
+                break;
+            };
+            if self.forward[a] != a
+                || self.forward[b] != b
+                || self.stamps[a] != a_stamp
+                || self.stamps[b] != b_stamp
+            {
+                continue;
+            }
+            let (position, _) = self.target(a, b);
+            if self.quadrics[a].add(&self.quadrics[b]).distance(&position) > max_distance {
+                continue;
+            }
+
+            // Cells on the edge go, and the rest around it mustn't flip over
+            let mut cells: Vec<usize> = self.point_cells[a]
+                .iter()
+                .chain(self.point_cells[b].iter())
+                .copied()
+                .filter(|&cell| self.alive[cell])
+                .collect();
+            cells.sort_unstable();
+            cells.dedup();
+            let flips = cells.iter().any(|&cell| {
+                let points = self.cell_points(cell);
+                if points.contains(&a) && points.contains(&b) {
+                    return false;
+                }
+                let before = points.map(|x| self.positions[x]);
+                let after = points.map(|x| {
+                    if x == a || x == b {
+                        position
+                    } else {
+                        self.positions[x]
+                    }
+                });
+                let (before, after) = (
+                    mesh::tetrahedron_normal(&before),
+                    mesh::tetrahedron_normal(&after),
+                );
+                // Cells which started out flat can't flip
+                before.norm() > 0.0
+                    && (before.dot(&after) <= 0.0 || after.norm() < 1e-3 * before.norm())
+            });
+            if flips || !self.keeps_manifold(a, b, &cells) {
+                continue;
+            }
+
+            self.forward[b] = a;
+            self.positions[a] = position;
+            self.quadrics[a] = self.quadrics[a].add(&self.quadrics[b]);
+            self.stamps[a] += 1;
+            for &cell in cells.iter() {
+                let points = self.cell_points(cell);
+                if (0..4).any(|i| points[i + 1..].contains(&points[i])) {
+                    self.alive[cell] = false;
+                    self.count -= 1;
+                }
+            }
+            self.point_cells[a] = cells.into_iter().filter(|&cell| self.alive[cell]).collect();
+            self.point_cells[b].clear();
+            self.push_edges(a, 0);
+        }
+    }
+
+    fn mesh(&self) -> (mesh::Mesh4, Vec<usize>) {
+        let mut new_index = vec![None; self.mesh.vertices.len()];
+        let mut sources = Vec::new();
+        let mut vertices = Vec::new();
+        let mut normals = Vec::new();
+        let mut colors = Vec::new();
+        let mut materials = Vec::new();
+        let mut indices = Vec::new();
+        for cell in (0..self.alive.len()).filter(|&cell| self.alive[cell]) {
+            for &i in self.mesh.indices[4 * cell..4 * cell + 4].iter() {
+                let i = i as usize;
+                let index = *new_index[i].get_or_insert_with(|| {
+                    vertices.push(self.positions[self.find(self.point_of[i])]);
+                    normals.push(self.mesh.normals[i]);
+                    colors.push(self.mesh.colors[i]);
+                    materials.push(self.mesh.materials[i]);
+                    sources.push(i);
+                    vertices.len() as u32 - 1
+                });
+                indices.push(index);
+            }
+        }
+        let mesh = mesh::Mesh4 {
+            materials,
+            ..mesh::Mesh4::new(vertices, normals, colors, indices)
+        };
+        (mesh, sources)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mesh;
+    use crate::mesh_builder::tests::{content, welded};
+
+    // The boundary has to stay closed and wound consistently
+    fn check(mesh: &mesh::Mesh4) -> mesh::Mesh4 {
+        let mut builder = welded(mesh);
+        builder.reorient = false;
+        builder.build().unwrap()
+    }
+
+    #[test]
+    fn halved() {
+        for original in [
+            mesh::Mesh4::glome(12),
+            mesh::Mesh4::spherinder(12),
+            mesh::Mesh4::duocylinder(12),
+        ] {
+            let target = original.num_tetrahedra / 2;
+            let (simplified, _) = original.simplified(target, f32::INFINITY);
+            assert!(simplified.num_tetrahedra <= target);
+            let (before, after) = (content(&check(&original)), content(&check(&simplified)));
+            assert!((after - before).abs() < 0.03 * before);
+        }
+    }
+
+    #[test]
+    fn max_distance() {
+        // A tesseract can't lose any cells without losing its corners, so it
+        // stays as it is
+        let cube = mesh::Mesh4::cube();
+        let (simplified, _) = cube.simplified(0, 0.01);
+        assert_eq!(simplified.num_tetrahedra, cube.num_tetrahedra);
+
+        let glome = mesh::Mesh4::glome(12);
+        let (simplified, _) = glome.simplified(0, 0.01);
+        // A glome can lose some, but only until it's noticeably less round
+        assert!(simplified.num_tetrahedra < glome.num_tetrahedra);
+        assert!(simplified.num_tetrahedra > glome.num_tetrahedra / 4);
+        let (before, after) = (content(&check(&glome)), content(&check(&simplified)));
+        assert!((after - before).abs() < 0.02 * before);
+    }
+
+    #[test]
+    fn sources() {
+        let original = mesh::Mesh4::glome(8);
+        let (simplified, sources) = original.simplified(original.num_tetrahedra / 2, f32::INFINITY);
+        assert_eq!(sources.len(), simplified.vertices.len());
+        for (i, &source) in sources.iter().enumerate() {
+            assert_eq!(simplified.colors[i], original.colors[source]);
+            assert_eq!(simplified.normals[i], original.normals[source]);
+        }
+    }
+}
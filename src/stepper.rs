@@ -0,0 +1,89 @@
+use crate::ga;
+use crate::na;
+use crate::physics;
+
+// Runs physics in fixed steps, however long each frame takes. Time left over
+// at the end of a frame is carried into the next one, and the renderer draws
+// each body that fraction of the way between its previous and current poses.
+pub struct Stepper {
+    // Never take more than this many steps in a single frame. Any more time than
+    // that is dropped, so after a long stall the simulation slows down, rather
+    // than falling further behind as each frame takes longer to simulate.
+    pub max_steps: u32,
+    remaining: f32,
+}
+
+impl Stepper {
+    pub fn new(max_steps: u32) -> Self {
+        Self {
+            max_steps,
+            remaining: 0.0,
+        }
+    }
+
+    // Add the time since the last frame, returning the number of steps to take
+    pub fn advance(&mut self, elapsed: f32, dt: f32) -> u32 {
+        self.remaining += elapsed;
+        let steps = (self.remaining / dt).floor() as u32;
+        if steps > self.max_steps {
+            self.remaining = 0.0;
+            self.max_steps
+        } else {
+            self.remaining -= steps as f32 * dt;
+            steps
+        }
+    }
+
+    // How far to draw each body between its previous and current poses
+    pub fn alpha(&self, dt: f32) -> f32 {
+        (self.remaining / dt).clamp(0.0, 1.0)
+    }
+}
+
+// Where a body was before the most recent step
+#[derive(Debug, Clone, Copy)]
+pub struct PreviousPose {
+    pub position: na::Vector4,
+    pub orientation: ga::Rotor4,
+}
+
+impl PreviousPose {
+    fn from_body(body: &physics::RigidBody) -> Self {
+        Self {
+            position: body.position,
+            orientation: body.orientation,
+        }
+    }
+}
+
+// Remember the current pose of every body. Call this before each step.
+pub fn save_poses(world: &mut hecs::World) {
+    let mut new_bodies = Vec::new();
+    for (entity, (body, previous)) in
+        world.query_mut::<(&physics::RigidBody, Option<&mut PreviousPose>)>()
+    {
+        match previous {
+            Some(previous) => *previous = PreviousPose::from_body(body),
+            None => new_bodies.push((entity, PreviousPose::from_body(body))),
+        }
+    }
+    for (entity, previous) in new_bodies {
+        world.insert_one(entity, previous).unwrap();
+    }
+}
+
+// The pose to draw a body at. Bodies which haven't been through a step yet are
+// drawn where they are.
+pub fn interpolate(
+    body: &physics::RigidBody,
+    previous: Option<&PreviousPose>,
+    alpha: f32,
+) -> (na::Vector4, ga::Rotor4) {
+    match previous {
+        Some(previous) => (
+            previous.position.lerp(&body.position, alpha),
+            previous.orientation.slerp(&body.orientation, alpha),
+        ),
+        None => (body.position, body.orientation),
+    }
+}
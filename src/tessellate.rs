@@ -0,0 +1,263 @@
+use crate::mesh;
+use crate::na;
+use crate::na::vec4;
+
+use std::f32::consts::{PI, TAU};
+
+// Round shapes, built from patches which each map a box of parameters onto part
+// of the boundary. Normals come from the shape, not the tetrahedra, so curved
+// parts are smooth shaded. Like the tesseract, each shape fits in a unit
+// tesseract centred on the origin, and resolution is the number of segments
+// around each full circle.
+impl mesh::Mesh4 {
+    pub fn glome(resolution: usize) -> Self {
+        let mut patches = Patches::new(resolution);
+        // Hopf coordinates - two circles, traded off against each other by eta
+        patches.add([0.25, 1.0, 1.0], |[eta, xi1, xi2]| {
+            let (eta, xi1, xi2) = (eta * PI / 2.0, xi1 * TAU, xi2 * TAU);
+            let normal = vec4(
+                eta.cos() * xi1.cos(),
+                eta.cos() * xi1.sin(),
+                eta.sin() * xi2.cos(),
+                eta.sin() * xi2.sin(),
+            );
+            (normal * 0.5, normal)
+        });
+        patches.build()
+    }
+
+    // A ball extruded along w
+    pub fn spherinder(resolution: usize) -> Self {
+        let mut patches = Patches::new(resolution);
+        patches.add([0.5, 1.0, 0.0], |[theta, phi, w]| {
+            let normal = sphere(theta, phi).insert_row(3, 0.0);
+            (normal * 0.5 + vec4(0.0, 0.0, 0.0, w - 0.5), normal)
+        });
+        for side in [-1.0, 1.0] {
+            patches.add([0.5, 1.0, 0.25], move |[theta, phi, rho]| {
+                let position = (sphere(theta, phi) * rho * 0.5).insert_row(3, side * 0.5);
+                (position, vec4(0.0, 0.0, 0.0, side))
+            });
+        }
+        patches.build()
+    }
+
+    // A disk extruded along z and w
+    pub fn cubinder(resolution: usize) -> Self {
+        let mut patches = Patches::new(resolution);
+        patches.add([1.0, 0.0, 0.0], |[phi, z, w]| {
+            let normal = vec4((phi * TAU).cos(), (phi * TAU).sin(), 0.0, 0.0);
+            (normal * 0.5 + vec4(0.0, 0.0, z - 0.5, w - 0.5), normal)
+        });
+        for axis in [2, 3] {
+            for side in [-1.0, 1.0] {
+                patches.add([1.0, 0.25, 0.0], move |[phi, rho, t]| {
+                    let mut position = disk(phi, rho * 0.5);
+                    position[axis] = side * 0.5;
+                    position[5 - axis] = t - 0.5;
+                    let mut normal = na::Vector4::zeros();
+                    normal[axis] = side;
+                    (position, normal)
+                });
+            }
+        }
+        patches.build()
+    }
+
+    // The product of two disks. Its boundary is two solid tori, each the product
+    // of one disk with the other's circle.
+    pub fn duocylinder(resolution: usize) -> Self {
+        let mut patches = Patches::new(resolution);
+        for first in [true, false] {
+            patches.add([1.0, 1.0, 0.25], move |[a, b, rho]| {
+                let circle = disk(a, 1.0);
+                let disk = disk(b, rho * 0.5);
+                if first {
+                    (circle * 0.5 + vec4(0.0, 0.0, disk[0], disk[1]), circle)
+                } else {
+                    let circle = vec4(0.0, 0.0, circle[0], circle[1]);
+                    (circle * 0.5 + disk, circle)
+                }
+            });
+        }
+        patches.build()
+    }
+
+    // Everything within a distance of a torus whose two circles are in the xy and
+    // zw planes. Unlike the tori below, it has no hole in any 3D slice through
+    // its centre.
+    pub fn tiger(resolution: usize) -> Self {
+        let (major, minor) = (0.35, 0.15);
+        let mut patches = Patches::new(resolution);
+        patches.add([1.0, 1.0, 0.5], move |[a, b, c]| {
+            let (a, b, c) = (a * TAU, b * TAU, c * TAU);
+            let normal = vec4(
+                c.cos() * a.cos(),
+                c.cos() * a.sin(),
+                c.sin() * b.cos(),
+                c.sin() * b.sin(),
+            );
+            let centre = vec4(a.cos(), a.sin(), b.cos(), b.sin()) * major;
+            (centre + normal * minor, normal)
+        });
+        patches.build()
+    }
+
+    // A 3D torus, thickened into a torus of its own and spun around the zw plane
+    pub fn ditorus(resolution: usize) -> Self {
+        let (major, middle, minor) = (0.3, 0.13, 0.07);
+        let mut patches = Patches::new(resolution);
+        patches.add([1.0, 0.5, 0.5], move |[a, b, c]| {
+            let (a, b, c) = (a * TAU, b * TAU, c * TAU);
+            let normal = vec4(
+                c.cos() * b.cos() * a.cos(),
+                c.cos() * b.cos() * a.sin(),
+                c.cos() * b.sin(),
+                c.sin(),
+            );
+            let centre = vec4(
+                (major + middle * b.cos()) * a.cos(),
+                (major + middle * b.cos()) * a.sin(),
+                middle * b.sin(),
+                0.0,
+            );
+            (centre + normal * minor, normal)
+        });
+        patches.build()
+    }
+
+    // A ball at w = -0.5, narrowing to a point at w = 0.5
+    pub fn cone(resolution: usize) -> Self {
+        let mut patches = Patches::new(resolution);
+        patches.add([0.5, 1.0, 0.25], |[theta, phi, t]| {
+            let direction = sphere(theta, phi);
+            let position = (direction * (1.0 - t) * 0.5).insert_row(3, t - 0.5);
+            (position, direction.insert_row(3, 0.5).normalize())
+        });
+        patches.add([0.5, 1.0, 0.25], |[theta, phi, rho]| {
+            let position = (sphere(theta, phi) * rho * 0.5).insert_row(3, -0.5);
+            (position, vec4(0.0, 0.0, 0.0, -1.0))
+        });
+        patches.build()
+    }
+}
+
+// A point on the unit sphere, for parameters from 0 to 1
+fn sphere(theta: f32, phi: f32) -> na::Vector3 {
+    let (theta, phi) = (theta * PI, phi * TAU);
+    na::Vector3::new(
+        theta.sin() * phi.cos(),
+        theta.sin() * phi.sin(),
+        theta.cos(),
+    )
+}
+
+// A point in the xy plane at the given radius
+fn disk(phi: f32, rho: f32) -> na::Vector4 {
+    vec4((phi * TAU).cos(), (phi * TAU).sin(), 0.0, 0.0) * rho
+}
+
+struct Patches {
+    resolution: usize,
+    // Each patch gets its own color
+    count: usize,
+    vertices: Vec<na::Vector4>,
+    normals: Vec<na::Vector4>,
+    colors: Vec<na::Vector4>,
+    indices: Vec<u32>,
+}
+
+impl Patches {
+    fn new(resolution: usize) -> Self {
+        Self {
+            resolution,
+            count: 0,
+            vertices: Vec::new(),
+            normals: Vec::new(),
+            colors: Vec::new(),
+            indices: Vec::new(),
+        }
+    }
+
+    // Add a patch which maps parameters from 0 to 1 to a position and normal.
+    // Each parameter is divided into turns * resolution segments, with at least
+    // one. The grid is split into tetrahedra the same way in every cube, so that
+    // they meet face to face.
+    fn add(&mut self, turns: [f32; 3], f: impl Fn([f32; 3]) -> (na::Vector4, na::Vector4)) {
+        let color = mesh::palette(self.count);
+        self.count += 1;
+        let segments = turns.map(|x| ((x * self.resolution as f32).round() as usize).max(1));
+        let index = |i: [usize; 3]| (i[0] * (segments[1] + 1) + i[1]) * (segments[2] + 1) + i[2];
+
+        let base = self.vertices.len() as u32;
+        for i in 0..=segments[0] {
+            for j in 0..=segments[1] {
+                for k in 0..=segments[2] {
+                    let (position, normal) = f([
+                        i as f32 / segments[0] as f32,
+                        j as f32 / segments[1] as f32,
+                        k as f32 / segments[2] as f32,
+                    ]);
+                    self.vertices.push(position);
+                    self.normals.push(normal);
+                    self.colors.push(color);
+                }
+            }
+        }
+
+        const ORDERS: [[usize; 3]; 6] = [
+            [0, 1, 2],
+            [0, 2, 1],
+            [1, 0, 2],
+            [1, 2, 0],
+            [2, 0, 1],
+            [2, 1, 0],
+        ];
+        for i in 0..segments[0] {
+            for j in 0..segments[1] {
+                for k in 0..segments[2] {
+                    for order in ORDERS {
+                        let mut corner = [i, j, k];
+                        let mut tetrahedron = [index(corner) as u32 + base; 4];
+                        for (n, axis) in order.into_iter().enumerate() {
+                            corner[axis] += 1;
+                            tetrahedron[n + 1] = index(corner) as u32 + base;
+                        }
+                        self.push_tetrahedron(tetrahedron);
+                    }
+                }
+            }
+        }
+    }
+
+    // Tetrahedra which come out completely flat are dropped, and the rest are
+    // wound to face the same way as their vertex normals
+    fn push_tetrahedron(&mut self, mut tetrahedron: [u32; 4]) {
+        let normal = mesh::tetrahedron_normal(&tetrahedron.map(|i| self.vertices[i as usize]));
+        if normal.norm() < 1e-9 {
+            return;
+        }
+        let outward: na::Vector4 = tetrahedron.iter().map(|&i| self.normals[i as usize]).sum();
+        if normal.dot(&outward) < 0.0 {
+            tetrahedron.swap(2, 3);
+        }
+        self.indices.extend(tetrahedron);
+    }
+
+    // The corners of tetrahedra at a pole only nearly meet, as the trigonometry
+    // isn't exact, so the ones squashed there are found by welding
+    fn build(self) -> mesh::Mesh4 {
+        let welded = mesh::weld(&self.vertices);
+        let indices = self
+            .indices
+            .chunks(4)
+            .filter(|tetrahedron| {
+                let corners = [0, 1, 2, 3].map(|i| welded[tetrahedron[i] as usize]);
+                (0..4).all(|i| !corners[i + 1..].contains(&corners[i]))
+            })
+            .flatten()
+            .copied()
+            .collect();
+        mesh::Mesh4::new(self.vertices, self.normals, self.colors, indices)
+    }
+}